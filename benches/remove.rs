@@ -0,0 +1,76 @@
+#[macro_use]
+extern crate criterion;
+extern crate rustyuploader;
+
+use criterion::Criterion;
+use std::path::PathBuf;
+
+use rustyuploader::lot::Data;
+
+/// Synthetic dist tree with 100k files and the folders containing them
+/// Paths are listed children first, the way the watcher reports removed folders
+fn removed_tree() -> Vec<String> {
+    let mut paths = vec![];
+
+    for cartridge in 0..5 {
+        for folder in 0..100 {
+            for sub_folder in 0..20 {
+                for file in 0..10 {
+                    paths.push(format!("/app_{}/cartridge/static/default/dist/js_{}/sub_{}/file_{}.js", cartridge, folder, sub_folder, file));
+                }
+                paths.push(format!("/app_{}/cartridge/static/default/dist/js_{}/sub_{}", cartridge, folder, sub_folder));
+            }
+            paths.push(format!("/app_{}/cartridge/static/default/dist/js_{}", cartridge, folder));
+        }
+        paths.push(format!("/app_{}/cartridge/static/default/dist", cartridge));
+    }
+
+    paths
+}
+
+/// Same files without any removed folder so nothing can be collapsed
+fn removed_files() -> Vec<String> {
+    removed_tree().into_iter().filter(|path| path.ends_with(".js")).collect()
+}
+
+fn update_tree(c: &mut Criterion) {
+    let paths = removed_tree();
+
+    c.bench_function("update 100k removed paths with folders", move |b| b.iter(|| {
+        let mut data = Data::new("");
+        data.set_removes(paths.clone());
+        data.update();
+        data
+    }));
+}
+
+fn update_files(c: &mut Criterion) {
+    let paths = removed_files();
+
+    c.bench_function("update 100k removed files", move |b| b.iter(|| {
+        let mut data = Data::new("");
+        data.set_removes(paths.clone());
+        data.update();
+        data
+    }));
+}
+
+fn push_remove_events(c: &mut Criterion) {
+    let paths: Vec<PathBuf> = removed_tree().into_iter().map(PathBuf::from).collect();
+
+    c.bench_function("push and update 100k remove events", move |b| b.iter(|| {
+        let mut data = Data::new("");
+        for path in paths.iter() {
+            data.push_remove(path.clone());
+        }
+        data.update();
+        data
+    }));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = update_tree, update_files, push_remove_events
+}
+criterion_main!(benches);
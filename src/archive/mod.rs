@@ -0,0 +1,224 @@
+extern crate zip;
+extern crate walkdir;
+extern crate sha2;
+
+use zip::{ZipWriter, ZipArchive, write::FileOptions, CompressionMethod, DateTime};
+use sha2::{Sha256, Digest};
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::io::{Write, Seek};
+use std::path::{Path, PathBuf};
+
+use super::filter::{Filter, Entry};
+use super::symlinks::{Symlinks, is_loop};
+
+/// Zip content together with the list of the zipped files
+pub struct Archive {
+    pub bytes: Vec<u8>,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// File inside an archive, the path is the entry name (e.g. app_core/cartridge/scripts/file.js)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Written next to an archive so CI can check that what was deployed equals what was built
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchiveManifest {
+    pub sha256: String,
+    pub size: u64,
+    pub files: Vec<ArchiveEntry>,
+}
+
+impl Archive {
+    pub fn manifest(&self) -> ArchiveManifest {
+        ArchiveManifest {
+            sha256: sha256(&self.bytes),
+            size: self.bytes.len() as u64,
+            files: self.entries.clone(),
+        }
+    }
+
+    /// Writes the zip at the path and its manifest at the same path with .manifest.json appended
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let manifest_path = format!("{}.manifest.json", path);
+        let json = serde_json::to_string_pretty(&self.manifest()).map_err(|e| format!("Unable to serialize archive manifest: {}", e))?;
+
+        fs::write(path, &self.bytes).map_err(|e| format!("Unable to write archive at path: {}\nError: {}", path, e))?;
+        fs::write(&manifest_path, json).map_err(|e| format!("Unable to write archive manifest at path: {}\nError: {}", manifest_path, e))
+    }
+}
+
+impl ArchiveManifest {
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Unable to parse archive manifest: {}", e)),
+            Err(e) => Err(format!("Unable to read archive manifest at path: {}\nError: {}", path, e)),
+        }
+    }
+}
+
+/// Folders at the root of a zip, the cartridges of a code version zip
+pub fn top_level_folders(bytes: &[u8]) -> Result<Vec<String>, String> {
+    let mut zip = ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| format!("Unable to read zip: {}", e))?;
+    let mut folders: Vec<String> = vec![];
+
+    for index in 0..zip.len() {
+        let entry = zip.by_index(index).map_err(|e| format!("Unable to read zip: {}", e))?;
+        let name = entry.name().to_owned();
+
+        // files at the root are not cartridges
+        if let Some(position) = name.find('/') {
+            let folder = name[..position].to_owned();
+            if !folder.is_empty() && !folders.contains(&folder) {
+                folders.push(folder);
+            }
+        }
+    }
+
+    Ok(folders)
+}
+
+/// Plain folder name a cartridge can have (e.g. app_core, int-paypal, bm_app.v2)
+/// Names like "..", "." or containing a separator would point outside of the code version
+pub fn is_cartridge_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".."
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Options of every entry
+/// Deterministic archives use a fixed timestamp and permissions so the same tree always gives the same bytes
+fn entry_options(deterministic: bool, is_dir: bool) -> FileOptions {
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    if !deterministic {
+        return options;
+    }
+
+    options
+        .last_modified_time(DateTime::default())
+        .unix_permissions(if is_dir { 0o755 } else { 0o644 })
+}
+
+/// Zips the folder keeping only the entries matching the filter, its content is put under name inside the zip
+/// The filter receives the entry path inside the zip (e.g. app_core/cartridge/scripts/file.js)
+pub fn zip_dir(path: &str, name: &str, filter: &Filter, deterministic: bool, symlinks: Symlinks) -> Result<Archive, String> {
+    zip_dirs_at(&vec![(Path::new(path), name)], filter, deterministic, symlinks)
+}
+
+/// Zips several folders of the base path into one archive, each one under its own name
+/// e.g. every cartridge laid out the way the code version expects them
+pub fn zip_dirs(base_path: &str, names: &Vec<String>, filter: &Filter, deterministic: bool, symlinks: Symlinks) -> Result<Archive, String> {
+    let mut names: Vec<&String> = names.iter().collect();
+    names.sort();
+
+    let folders: Vec<(PathBuf, &str)> = names.into_iter()
+        .map(|name| (Path::new(base_path).join(name), name.as_str()))
+        .collect();
+
+    zip_dirs_at(&folders.iter().map(|(path, name)| (path.as_path(), *name)).collect(), filter, deterministic, symlinks)
+}
+
+fn zip_dirs_at(folders: &Vec<(&Path, &str)>, filter: &Filter, deterministic: bool, symlinks: Symlinks) -> Result<Archive, String> {
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let mut entries: Vec<ArchiveEntry> = vec![];
+
+    for (path, name) in folders.iter() {
+        if !path.is_dir() {
+            return Err(format!("Unable to zip folder: {:?} is not a folder", path));
+        }
+
+        zip.add_directory(format!("{}/", name), entry_options(deterministic, true))
+            .map_err(|e| format!("Unable to zip {}: {}", name, e))?;
+        zip_folder(&mut zip, path, name, filter, deterministic, symlinks, &mut entries)?;
+    }
+
+    zip.finish()
+        .map(|cursor| Archive { bytes: cursor.into_inner(), entries: entries })
+        .map_err(|e| format!("Unable to zip files: {}", e))
+}
+
+/// Adds the content of the folder under the name
+/// Entries that cannot be named in a zip (non UTF-8 paths), link loops and links left out by the policy
+/// are skipped with a warning, unreadable files are errors
+fn zip_folder<W: Write + Seek>(zip: &mut ZipWriter<W>, root: &Path, name: &str, filter: &Filter, deterministic: bool, symlinks: Symlinks, entries: &mut Vec<ArchiveEntry>) -> Result<(), String> {
+    let walker = symlinks.walk(root).min_depth(1).sort_by(|a, b| a.file_name().cmp(b.file_name()));
+
+    for entry in walker.into_iter() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if is_loop(&e) => {
+                println!("Skipping {:?}: the symbolic link loops back to a parent folder", e.path());
+                continue;
+            },
+            Err(e) => return Err(format!("Unable to read folder: {:?}\nError: {}", root, e)),
+        };
+        let path = entry.path();
+
+        if !symlinks.accepts(&entry) {
+            println!("Skipping symbolic link {:?}", path);
+            continue;
+        }
+
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+
+        let components: Option<Vec<&str>> = relative.components().map(|component| component.as_os_str().to_str()).collect();
+        let entry_name = match components {
+            Some(components) => format!("{}/{}", name, components.join("/")),
+            None => {
+                println!("Skipping {:?}: the path is not valid UTF-8", path);
+                continue;
+            },
+        };
+
+        if !filter.matches(&Entry::new(&entry_name, Some(path))) {
+            continue;
+        }
+
+        if entry.file_type().is_dir() {
+            zip.add_directory(format!("{}/", entry_name), entry_options(deterministic, true))
+                .map_err(|e| format!("Unable to zip {}: {}", entry_name, e))?;
+        } else if path.is_file() {
+            let bytes = fs::read(path).map_err(|e| format!("Unable to read file at path: {:?}\nError: {}", path, e))?;
+
+            zip.start_file(entry_name.as_str(), entry_options(deterministic, false))
+                .and_then(|()| zip.write_all(bytes.as_slice()).map_err(|e| e.into()))
+                .map_err(|e| format!("Unable to zip {}: {}", entry_name, e))?;
+            entries.push(ArchiveEntry { path: entry_name, size: bytes.len() as u64, sha256: sha256(&bytes) });
+        }
+    }
+
+    Ok(())
+}
+
+/// Zips the given files, each entry is a pair of the name inside the zip and the content
+/// The content is read by the caller so it knows exactly what was sent
+pub fn zip_files(files: &Vec<(String, Vec<u8>)>) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, bytes) in files.iter() {
+        zip.start_file(name.as_str(), options).map_err(|e| format!("Unable to zip {}: {}", name, e))?;
+        zip.write_all(bytes.as_slice()).map_err(|e| format!("Unable to zip {}: {}", name, e))?;
+    }
+
+    zip.finish()
+        .map(|cursor| cursor.into_inner())
+        .map_err(|e| format!("Unable to zip files: {}", e))
+}
+
+/// Hex encoded SHA-256 of the bytes
+pub fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,238 @@
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use zip::ZipArchive;
+
+use super::*;
+use super::super::filter::Filter;
+use super::super::symlinks::Symlinks;
+
+fn temp_folder(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustyuploader-archive-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+
+    path
+}
+
+fn write(path: PathBuf, content: &str) -> () {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// Entry names and file contents of a zip, in the order they were written
+fn read_zip(bytes: &[u8]) -> Vec<(String, String)> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+    (0..zip.len()).map(|index| {
+        let mut entry = zip.by_index(index).unwrap();
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+
+        (entry.name().to_owned(), content)
+    }).collect()
+}
+
+#[test]
+fn zip_dir_with_the_name_in_its_parents() {
+    let base = temp_folder("prefix");
+    let cartridge = base.join("app_core/cartridges/app_core");
+    write(cartridge.join("cartridge/scripts/home.js"), "home");
+    write(cartridge.join("package.json"), "{}");
+
+    let archive = zip_dir(cartridge.to_str().unwrap(), "app_core", &Filter::everything(), false, Symlinks::Content).unwrap();
+
+    assert_eq!(read_zip(&archive.bytes), vec![
+        ("app_core/".to_owned(), "".to_owned()),
+        ("app_core/cartridge/".to_owned(), "".to_owned()),
+        ("app_core/cartridge/scripts/".to_owned(), "".to_owned()),
+        ("app_core/cartridge/scripts/home.js".to_owned(), "home".to_owned()),
+        ("app_core/package.json".to_owned(), "{}".to_owned()),
+    ]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn zip_dirs_round_trip() {
+    let base = temp_folder("round-trip");
+    write(base.join("app_storefront/cartridge/templates/home.isml"), "<isif>");
+    write(base.join("app_core/cartridge/scripts/home.js"), "home");
+    write(base.join("app_core/node_modules/lib/index.js"), "lib");
+
+    let filter = Filter::exclude(&vec!["node_modules".to_owned()]);
+    let names = vec!["app_storefront".to_owned(), "app_core".to_owned()];
+    let archive = zip_dirs(base.to_str().unwrap(), &names, &filter, false, Symlinks::Content).unwrap();
+
+    let files: Vec<(String, String)> = read_zip(&archive.bytes).into_iter()
+        .filter(|(name, _)| !name.ends_with("/"))
+        .collect();
+    assert_eq!(files, vec![
+        ("app_core/cartridge/scripts/home.js".to_owned(), "home".to_owned()),
+        ("app_storefront/cartridge/templates/home.isml".to_owned(), "<isif>".to_owned()),
+    ]);
+
+    let paths: Vec<String> = archive.entries.iter().map(|entry| entry.path.clone()).collect();
+    assert_eq!(paths, files.into_iter().map(|(name, _)| name).collect::<Vec<String>>());
+    assert_eq!(archive.entries[0].sha256, sha256(b"home"));
+    assert_eq!(top_level_folders(&archive.bytes).unwrap(), vec!["app_core".to_owned(), "app_storefront".to_owned()]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn deterministic_zips_are_reproducible() {
+    let base = temp_folder("deterministic");
+    write(base.join("app/b/second.js"), "second");
+    write(base.join("app/a.js"), "first");
+
+    let first = zip_dirs(base.to_str().unwrap(), &vec!["app".to_owned()], &Filter::everything(), true, Symlinks::Content).unwrap();
+    // rewriting a file with the same content does not change the archive
+    write(base.join("app/a.js"), "first");
+    let second = zip_dirs(base.to_str().unwrap(), &vec!["app".to_owned()], &Filter::everything(), true, Symlinks::Content).unwrap();
+
+    assert!(first.bytes == second.bytes);
+    assert_eq!(first.manifest(), second.manifest());
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn saved_manifest_matches_the_archive() {
+    let base = temp_folder("manifest");
+    write(base.join("app/a.js"), "first");
+
+    let archive = zip_dir(base.join("app").to_str().unwrap(), "app", &Filter::everything(), true, Symlinks::Content).unwrap();
+    let path = base.join("app.zip");
+    archive.save(path.to_str().unwrap()).unwrap();
+
+    let manifest = ArchiveManifest::load(format!("{}.manifest.json", path.to_str().unwrap()).as_str()).unwrap();
+    assert_eq!(manifest.sha256, sha256(&fs::read(&path).unwrap()));
+    assert_eq!(manifest.files, archive.entries);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn missing_folder_is_an_error() {
+    let base = temp_folder("missing");
+
+    assert!(zip_dir(base.join("app").to_str().unwrap(), "app", &Filter::everything(), false, Symlinks::Content).is_err());
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_entries_are_skipped() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let base = temp_folder("non-utf8");
+    write(base.join("app/valid.js"), "valid");
+    fs::write(base.join("app").join(OsStr::from_bytes(b"invalid-\xff.js")), "invalid").unwrap();
+
+    let archive = zip_dir(base.join("app").to_str().unwrap(), "app", &Filter::everything(), false, Symlinks::Content).unwrap();
+
+    assert_eq!(read_zip(&archive.bytes), vec![
+        ("app/".to_owned(), "".to_owned()),
+        ("app/valid.js".to_owned(), "valid".to_owned()),
+    ]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn zip_files_round_trip() {
+    let files = vec![("app/a.js".to_owned(), b"first".to_vec()), ("app/b/c.js".to_owned(), vec![])];
+
+    assert_eq!(read_zip(&zip_files(&files).unwrap()), vec![
+        ("app/a.js".to_owned(), "first".to_owned()),
+        ("app/b/c.js".to_owned(), "".to_owned()),
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_policies() {
+    use std::os::unix::fs::symlink;
+
+    let base = temp_folder("symlinks");
+    write(base.join("shared/assets/logo.svg"), "<svg>");
+    write(base.join("shared/readme.md"), "shared");
+    write(base.join("app/home.js"), "home");
+    symlink(base.join("shared/assets"), base.join("app/assets")).unwrap();
+    symlink(base.join("shared/readme.md"), base.join("app/readme.md")).unwrap();
+    // loops back to the cartridge
+    symlink(base.join("app"), base.join("app/self")).unwrap();
+
+    let files = |symlinks: Symlinks| -> Vec<(String, String)> {
+        let archive = zip_dir(base.join("app").to_str().unwrap(), "app", &Filter::everything(), false, symlinks).unwrap();
+        read_zip(&archive.bytes).into_iter().filter(|(name, _)| !name.ends_with("/")).collect()
+    };
+
+    assert_eq!(files(Symlinks::Follow), vec![
+        ("app/assets/logo.svg".to_owned(), "<svg>".to_owned()),
+        ("app/home.js".to_owned(), "home".to_owned()),
+        ("app/readme.md".to_owned(), "shared".to_owned()),
+    ]);
+    assert_eq!(files(Symlinks::Content), vec![
+        ("app/home.js".to_owned(), "home".to_owned()),
+        ("app/readme.md".to_owned(), "shared".to_owned()),
+    ]);
+    assert_eq!(files(Symlinks::Skip), vec![
+        ("app/home.js".to_owned(), "home".to_owned()),
+    ]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn deterministic_entries_ignore_file_times_and_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let base = temp_folder("fixed-options");
+    write(base.join("first/app/scripts/home.js"), "home");
+    write(base.join("second/app/scripts/home.js"), "home");
+    fs::set_permissions(base.join("second/app/scripts/home.js"), fs::Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(base.join("second/app/scripts"), fs::Permissions::from_mode(0o700)).unwrap();
+    fs::File::options().write(true).open(base.join("second/app/scripts/home.js")).unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000)).unwrap();
+
+    let zip = |folder: &str| zip_dir(base.join(folder).join("app").to_str().unwrap(), "app", &Filter::everything(), true, Symlinks::Content).unwrap();
+    let first = zip("first");
+    let second = zip("second");
+    assert!(first.bytes == second.bytes);
+
+    let mut archive = ZipArchive::new(Cursor::new(second.bytes.as_slice())).unwrap();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).unwrap();
+        let time = entry.last_modified();
+        assert_eq!((time.year(), time.month(), time.day(), time.hour(), time.minute(), time.second()), (1980, 1, 1, 0, 0, 0));
+        assert_eq!(entry.unix_mode().unwrap() & 0o777, if entry.is_dir() { 0o755 } else { 0o644 });
+    }
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn manifest_lists_the_archive_and_its_files() {
+    let base = temp_folder("sha256");
+    write(base.join("app/a.js"), "abc");
+    write(base.join("app/b/c.js"), "");
+
+    let archive = zip_dir(base.join("app").to_str().unwrap(), "app", &Filter::everything(), true, Symlinks::Content).unwrap();
+    let manifest = archive.manifest();
+
+    assert_eq!(manifest.sha256, sha256(&archive.bytes));
+    assert_eq!(manifest.size, archive.bytes.len() as u64);
+    assert_eq!(manifest.files, vec![
+        ArchiveEntry { path: "app/a.js".to_owned(), size: 3, sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_owned() },
+        ArchiveEntry { path: "app/b/c.js".to_owned(), size: 0, sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_owned() },
+    ]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
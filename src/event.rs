@@ -0,0 +1,61 @@
+use notify::DebouncedEvent;
+
+use std::path::{Path, PathBuf};
+
+/// File system change consumed by lot::Collection
+/// Independent from the watcher backend so any event source can feed a collection
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileEvent {
+    Create(PathBuf),
+    Write(PathBuf),
+    Remove(PathBuf),
+    Rename(PathBuf, PathBuf),
+    /// Permissions changed, the content is the same
+    Chmod(PathBuf),
+    /// Events may have been lost and the files have to be checked again
+    Rescan,
+    Error(String, Option<PathBuf>),
+}
+
+impl FileEvent {
+    /// Converts notify events
+    /// Notice events only announce a Write or Remove that follows so they are dropped
+    pub fn from_notify(event: DebouncedEvent) -> Option<Self> {
+        match event {
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => None,
+            DebouncedEvent::Create(path) => Some(FileEvent::Create(path)),
+            DebouncedEvent::Write(path) => Some(FileEvent::Write(path)),
+            DebouncedEvent::Remove(path) => Some(FileEvent::Remove(path)),
+            DebouncedEvent::Rename(path, new_path) => Some(FileEvent::Rename(path, new_path)),
+            DebouncedEvent::Chmod(path) => Some(FileEvent::Chmod(path)),
+            DebouncedEvent::Rescan => Some(FileEvent::Rescan),
+            DebouncedEvent::Error(error, path) => Some(FileEvent::Error(format!("{}", error), path)),
+        }
+    }
+
+    /// Replaces every path of the event
+    pub fn map_paths<F: Fn(PathBuf) -> PathBuf>(self, map: F) -> Self {
+        match self {
+            FileEvent::Create(path) => FileEvent::Create(map(path)),
+            FileEvent::Write(path) => FileEvent::Write(map(path)),
+            FileEvent::Remove(path) => FileEvent::Remove(map(path)),
+            FileEvent::Rename(path, new_path) => FileEvent::Rename(map(path), map(new_path)),
+            FileEvent::Chmod(path) => FileEvent::Chmod(map(path)),
+            FileEvent::Error(message, path) => FileEvent::Error(message, path.map(map)),
+            FileEvent::Rescan => FileEvent::Rescan,
+        }
+    }
+
+    /// Path the event refers to, the source for renames
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            FileEvent::Create(path)
+            | FileEvent::Write(path)
+            | FileEvent::Remove(path)
+            | FileEvent::Rename(path, _)
+            | FileEvent::Chmod(path) => Some(path.as_path()),
+            FileEvent::Error(_, path) => path.as_ref().map(|path| path.as_path()),
+            FileEvent::Rescan => None,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+extern crate glob;
+extern crate regex;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::lot::is_inside;
+
+/// Path checked by a filter
+/// full_path is the local file, None for paths that no longer exist locally (e.g. removed files)
+pub struct Entry<'a> {
+    pub path: &'a str,
+    pub full_path: Option<&'a Path>,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new(path: &'a str, full_path: Option<&'a Path>) -> Self {
+        Entry { path: path, full_path: full_path }
+    }
+
+    fn metadata(&self) -> Option<fs::Metadata> {
+        self.full_path.and_then(|path| fs::metadata(path).ok())
+    }
+
+    /// Collected paths start with a slash, zip entries do not, rules see both without it
+    fn normalized(&self) -> &str {
+        self.path.trim_start_matches("/")
+    }
+
+    fn extension(&self) -> Option<&str> {
+        self.path.split("/").last()
+            .and_then(|name| name.rsplit(".").next().filter(|extension| extension.len() < name.len()))
+    }
+
+    /// Paths that are gone are folders when they have no extension, the same guess lot::Data makes
+    fn is_folder(&self) -> bool {
+        match self.full_path {
+            Some(path) if path.exists() => path.is_dir(),
+            _ => self.extension().is_none(),
+        }
+    }
+}
+
+/// Predicate used to select files and folders
+/// Entries without local metadata always pass the size and modification time checks
+/// so that removed files are still removed remotely
+#[derive(Clone)]
+pub enum Filter {
+    /// Path contains the text anywhere
+    Contains(String),
+    /// Path is the folder or inside it
+    Under(String),
+    /// Matched against the path without its leading slash
+    Glob(glob::Pattern),
+    /// Matched against the path without its leading slash
+    Regex(regex::Regex),
+    /// File name ends with one of the extensions (without the dot)
+    /// Folders always match so their removal and their zip entries are kept
+    Extension(Vec<String>),
+    MaxSize(u64),
+    MinSize(u64),
+    ModifiedAfter(SystemTime),
+    ModifiedBefore(SystemTime),
+    Custom(Arc<dyn Fn(&Entry) -> bool + Send + Sync>),
+    Not(Box<Filter>),
+    All(Vec<Filter>),
+    Any(Vec<Filter>),
+}
+
+impl Filter {
+    /// Accepts everything
+    pub fn everything() -> Self {
+        Filter::All(vec![])
+    }
+
+    /// Paths containing any of the given texts
+    pub fn include(paths: &Vec<String>) -> Self {
+        Filter::Any(paths.iter().map(|path| Filter::Contains(path.to_owned())).collect())
+    }
+
+    /// Paths containing none of the given texts
+    pub fn exclude(paths: &Vec<String>) -> Self {
+        Filter::include(paths).not()
+    }
+
+    /// Paths inside any of the given folders
+    /// Compares whole path components, unlike include "app" does not match "/app_core"
+    pub fn under(folders: &Vec<String>) -> Self {
+        Filter::Any(folders.iter().map(|folder| Filter::Under(folder.to_owned())).collect())
+    }
+
+    /// Temporary files written by editors while saving
+    /// vim (.swp, ~, 4913), emacs (.#file), IntelliJ (.___jb_tmp___, .___jb_old___)
+    pub fn editor_temp_files() -> Self {
+        Filter::Regex(regex::Regex::new(r"(^|/)(4913|\.#[^/]*)$|\.sw[pox]$|~$|\.___jb_(tmp|old)___$").unwrap())
+    }
+
+    pub fn glob(pattern: &str) -> Result<Self, String> {
+        glob::Pattern::new(pattern)
+            .map(Filter::Glob)
+            .map_err(|e| format!("Invalid glob pattern: {}\nError: {}", pattern, e))
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        regex::Regex::new(pattern)
+            .map(Filter::Regex)
+            .map_err(|e| format!("Invalid regex: {}\nError: {}", pattern, e))
+    }
+
+    pub fn extensions(extensions: &[&str]) -> Self {
+        Filter::Extension(extensions.iter().map(|extension| extension.trim_start_matches(".").to_owned()).collect())
+    }
+
+    pub fn custom<F: Fn(&Entry) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        Filter::Custom(Arc::new(predicate))
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::All(mut filters) => {
+                filters.push(other);
+                Filter::All(filters)
+            },
+            filter => Filter::All(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Any(mut filters) => {
+                filters.push(other);
+                Filter::Any(filters)
+            },
+            filter => Filter::Any(vec![filter, other]),
+        }
+    }
+
+    pub fn not(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Filter::Contains(text) => entry.path.contains(text.as_str()),
+            Filter::Under(folder) => is_inside(entry.normalized(), folder.trim_start_matches("/")),
+            Filter::Glob(pattern) => pattern.matches(entry.normalized()),
+            Filter::Regex(regex) => regex.is_match(entry.normalized()),
+            Filter::Extension(_) if entry.is_folder() => true,
+            Filter::Extension(extensions) => entry.extension()
+                .map(|extension| extensions.iter().any(|item| item == extension))
+                .unwrap_or(false),
+            Filter::MaxSize(size) => entry.metadata().map(|metadata| metadata.len() <= *size).unwrap_or(true),
+            Filter::MinSize(size) => entry.metadata().map(|metadata| metadata.len() >= *size).unwrap_or(true),
+            Filter::ModifiedAfter(time) => entry.metadata()
+                .and_then(|metadata| metadata.modified().ok())
+                .map(|modified| modified > *time)
+                .unwrap_or(true),
+            Filter::ModifiedBefore(time) => entry.metadata()
+                .and_then(|metadata| metadata.modified().ok())
+                .map(|modified| modified < *time)
+                .unwrap_or(true),
+            Filter::Custom(predicate) => predicate(entry),
+            Filter::Not(filter) => !filter.matches(entry),
+            Filter::All(filters) => filters.iter().all(|filter| filter.matches(entry)),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(entry)),
+        }
+    }
+
+    pub fn matches_path(&self, path: &str) -> bool {
+        self.matches(&Entry::new(path, None))
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Contains(text) => write!(f, "Contains({:?})", text),
+            Filter::Under(folder) => write!(f, "Under({:?})", folder),
+            Filter::Glob(pattern) => write!(f, "Glob({:?})", pattern.as_str()),
+            Filter::Regex(regex) => write!(f, "Regex({:?})", regex.as_str()),
+            Filter::Extension(extensions) => write!(f, "Extension({:?})", extensions),
+            Filter::MaxSize(size) => write!(f, "MaxSize({})", size),
+            Filter::MinSize(size) => write!(f, "MinSize({})", size),
+            Filter::ModifiedAfter(time) => write!(f, "ModifiedAfter({:?})", time),
+            Filter::ModifiedBefore(time) => write!(f, "ModifiedBefore({:?})", time),
+            Filter::Custom(_) => write!(f, "Custom"),
+            Filter::Not(filter) => write!(f, "Not({:?})", filter),
+            Filter::All(filters) => write!(f, "All({:?})", filters),
+            Filter::Any(filters) => write!(f, "Any({:?})", filters),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+use std::process::Command;
+
+use super::event::FileEvent;
+
+/// Commit checked out in the repository containing the folder
+/// Fails when the folder is not part of a git repository
+pub fn head(folder: &Path) -> Result<String, String> {
+    run(folder, &["rev-parse", "HEAD"]).map(|output| output.trim().to_owned())
+}
+
+/// Changes inside the folder between the commit and the working tree, untracked files included
+/// Renames and copies are detected by git, the events are ordered removes, renames, creates and writes
+/// so that feeding them to a collection in order gives the right operations
+pub fn changes_since(folder: &Path, since: &str) -> Result<Vec<FileEvent>, String> {
+    let mut removed: Vec<FileEvent> = vec![];
+    let mut renamed: Vec<FileEvent> = vec![];
+    let mut written: Vec<FileEvent> = vec![];
+
+    // paths are relative to the folder, the folder does not have to be the repository root
+    let diff = run(folder, &["diff", "--relative", "--name-status", "-M", "--find-copies", "-z", since, "--"])?;
+    let mut fields = diff.split('\0').filter(|field| !field.is_empty());
+
+    while let Some(status) = fields.next() {
+        let path = match fields.next() {
+            Some(path) => folder.join(path),
+            None => break,
+        };
+
+        match status.chars().next() {
+            Some('A') => written.push(FileEvent::Create(path)),
+            Some('D') => removed.push(FileEvent::Remove(path)),
+            Some('R') | Some('C') => {
+                let new_path = match fields.next() {
+                    Some(new_path) => folder.join(new_path),
+                    None => break,
+                };
+
+                if status.starts_with('C') {
+                    written.push(FileEvent::Create(new_path));
+                    continue;
+                }
+
+                // R100 is a pure rename, a lower score means the content changed as well
+                if status != "R100" {
+                    written.push(FileEvent::Write(new_path.clone()));
+                }
+                renamed.push(FileEvent::Rename(path, new_path));
+            },
+            // modified, type changed or unmerged
+            Some(_) => written.push(FileEvent::Write(path)),
+            None => (),
+        }
+    }
+
+    let untracked = run(folder, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+    written.extend(untracked.split('\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| FileEvent::Create(folder.join(path))));
+
+    removed.extend(renamed);
+    removed.extend(written);
+
+    Ok(removed)
+}
+
+fn run(folder: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(folder)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests;
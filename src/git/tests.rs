@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::{head, changes_since, run};
+use super::super::event::FileEvent;
+
+fn repository(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustyuploader-git-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(path.join("app")).unwrap();
+
+    run(&path, &["init", "-q"]).unwrap();
+    run(&path, &["config", "user.email", "test@example.com"]).unwrap();
+    run(&path, &["config", "user.name", "test"]).unwrap();
+
+    path
+}
+
+fn commit(path: &PathBuf) -> String {
+    run(path, &["add", "-A"]).unwrap();
+    run(path, &["commit", "-q", "-m", "test"]).unwrap();
+
+    head(path).unwrap()
+}
+
+const CONTENT: &str = "line 1\nline 2\nline 3\nline 4\nline 5\nline 6\nline 7\nline 8\n";
+
+#[test]
+fn renamed_paths_with_spaces() {
+    let path = repository("rename");
+    fs::write(path.join("app/old name.js"), CONTENT.replace("line", "name")).unwrap();
+    fs::write(path.join("app/edited.js"), CONTENT).unwrap();
+    fs::write(path.join("app/removed.js"), "removed").unwrap();
+    let since = commit(&path);
+
+    fs::rename(path.join("app/old name.js"), path.join("app/new name.js")).unwrap();
+    fs::rename(path.join("app/edited.js"), path.join("app/edited copy.js")).unwrap();
+    fs::write(path.join("app/edited copy.js"), format!("{}line 9\n", CONTENT)).unwrap();
+    fs::remove_file(path.join("app/removed.js")).unwrap();
+    fs::write(path.join("app/untracked file.js"), "new").unwrap();
+    run(&path, &["add", "-A"]).unwrap();
+    run(&path, &["rm", "-q", "--cached", "app/untracked file.js"]).unwrap();
+
+    assert_eq!(changes_since(&path, since.as_str()).unwrap(), vec![
+        FileEvent::Remove(path.join("app/removed.js")),
+        FileEvent::Rename(path.join("app/edited.js"), path.join("app/edited copy.js")),
+        FileEvent::Rename(path.join("app/old name.js"), path.join("app/new name.js")),
+        FileEvent::Write(path.join("app/edited copy.js")),
+        FileEvent::Create(path.join("app/untracked file.js")),
+    ]);
+
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn copied_paths_are_created() {
+    let path = repository("copy");
+    fs::write(path.join("app/source.js"), CONTENT).unwrap();
+    let since = commit(&path);
+
+    fs::write(path.join("app/copy of source.js"), CONTENT).unwrap();
+    fs::write(path.join("app/source.js"), format!("{}line 9\n", CONTENT)).unwrap();
+    commit(&path);
+
+    assert_eq!(changes_since(&path, since.as_str()).unwrap(), vec![
+        FileEvent::Create(path.join("app/copy of source.js")),
+        FileEvent::Write(path.join("app/source.js")),
+    ]);
+
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn paths_are_relative_to_the_folder() {
+    let path = repository("relative");
+    fs::write(path.join("outside.js"), "outside").unwrap();
+    fs::write(path.join("app/inside.js"), "inside").unwrap();
+    let since = commit(&path);
+
+    fs::write(path.join("outside.js"), "changed").unwrap();
+    fs::write(path.join("app/inside.js"), "changed").unwrap();
+
+    let folder = path.join("app");
+    assert_eq!(changes_since(&folder, since.as_str()).unwrap(), vec![FileEvent::Write(folder.join("inside.js"))]);
+
+    fs::remove_dir_all(&path).unwrap();
+}
@@ -1,5 +1,6 @@
 extern crate serde;
 use serde::Deserialize;
+use std::path::Path;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
@@ -10,6 +11,24 @@ pub struct Config {
     cartridges: Option<Vec<String>>,
     ignore_list: Option<Vec<String>>,
     code_version: String,
+    debounce_ms: Option<u64>,
+    quiet_period_ms: Option<u64>,
+    max_batch_age_ms: Option<u64>,
+    settle_ms: Option<u64>,
+    change_sets_path: Option<String>,
+    watch_backend: Option<String>,
+    poll_interval_ms: Option<u64>,
+    rename_detection: Option<String>,
+    queue_path: Option<String>,
+    probe_interval_ms: Option<u64>,
+    startup_sync: Option<String>,
+    manifest_path: Option<String>,
+    git_sync: Option<bool>,
+    zip_min_files: Option<usize>,
+    zip_min_bytes: Option<u64>,
+    deterministic_zips: Option<bool>,
+    archives_path: Option<String>,
+    symlinks: Option<String>,
 }
 
 impl Config {
@@ -48,6 +67,97 @@ impl Config {
 
         vec![]
     }
+
+    /// Delay used by the watcher to debounce file system events
+    pub fn get_debounce_ms(&self) -> u64 {
+        self.debounce_ms.unwrap_or(1000)
+    }
+
+    /// How long the watcher has to be quiet before a batch is pushed
+    pub fn get_quiet_period_ms(&self) -> u64 {
+        self.quiet_period_ms.unwrap_or(500)
+    }
+
+    /// A batch is pushed once it gets this old even if events keep coming
+    pub fn get_max_batch_age_ms(&self) -> u64 {
+        self.max_batch_age_ms.unwrap_or(10000)
+    }
+
+    /// Interval used to check that files stopped changing size before uploading
+    /// Settle mode is disabled when not set
+    pub fn get_settle_ms(&self) -> Option<u64> {
+        self.settle_ms
+    }
+
+    /// Folder where every watch batch is saved as a change set before it is pushed
+    pub fn get_change_sets_path(&self) -> Option<String> {
+        self.change_sets_path.clone()
+    }
+
+    /// "notify" (default) or "polling" for network file systems and container volumes
+    pub fn get_watch_backend(&self) -> String {
+        self.watch_backend.clone().unwrap_or("notify".to_owned())
+    }
+
+    pub fn get_poll_interval_ms(&self) -> u64 {
+        self.poll_interval_ms.unwrap_or(2000)
+    }
+
+    /// "inode" (default on unix) or "hash", used by the polling backend to detect renamed files
+    pub fn get_rename_detection(&self) -> String {
+        self.rename_detection.clone().unwrap_or("inode".to_owned())
+    }
+
+    /// File keeping the operations that could not be pushed while the Sandbox was unreachable
+    /// Saved next to the config file by default, not in the working directory
+    pub fn get_queue_path(&self, config_dir: &Path) -> String {
+        self.queue_path.clone().unwrap_or(config_dir.join("upload_queue.json").to_string_lossy().into_owned())
+    }
+
+    pub fn get_probe_interval_ms(&self) -> u64 {
+        self.probe_interval_ms.unwrap_or(30000)
+    }
+
+    /// What the local files are compared with before watching: "manifest", "remote" or nothing
+    pub fn get_startup_sync(&self) -> Option<String> {
+        self.startup_sync.clone()
+    }
+
+    /// File keeping the files known to be on the Sandbox between runs
+    /// Saved next to the config file by default, like the offline queue
+    pub fn get_manifest_path(&self, config_dir: &Path) -> String {
+        self.manifest_path.clone().unwrap_or(config_dir.join("synced_manifest.json").to_string_lossy().into_owned())
+    }
+
+    /// Push the git changes instead of the file events when HEAD moves during a watch
+    pub fn get_git_sync(&self) -> bool {
+        self.git_sync.unwrap_or(true)
+    }
+
+    /// Uploads of a batch are sent as one zip starting with this many files
+    pub fn get_zip_min_files(&self) -> usize {
+        self.zip_min_files.unwrap_or(20)
+    }
+
+    /// or when they add up to this many bytes
+    pub fn get_zip_min_bytes(&self) -> u64 {
+        self.zip_min_bytes.unwrap_or(1024 * 1024)
+    }
+
+    /// Cartridge zips with sorted entries, a fixed timestamp and normalized permissions
+    pub fn get_deterministic_zips(&self) -> bool {
+        self.deterministic_zips.unwrap_or(false)
+    }
+
+    /// Folder where every pushed cartridge zip is saved together with its manifest
+    pub fn get_archives_path(&self) -> Option<String> {
+        self.archives_path.clone()
+    }
+
+    /// Symbolic link policy: "follow", "content" or "skip"
+    pub fn get_symlinks(&self) -> String {
+        self.symlinks.clone().unwrap_or("content".to_owned())
+    }
 }
 
 pub fn parse_config(json: &str) -> Config {
@@ -3,8 +3,20 @@ extern crate rustydav;
 mod loader;
 mod json;
 mod watch;
+mod poll;
 mod sandbox;
-mod lot;
+// public only for the benchmarks, see Cargo.toml
+#[cfg(feature = "bench")]
+pub mod lot;
+#[cfg(not(feature = "bench"))]
+pub(crate) mod lot;
 mod time;
 mod archive;
+mod manifest;
+mod queue;
+mod git;
+mod symlinks;
+pub mod filter;
+pub mod event;
 pub mod upload;
+pub mod shutdown;
@@ -25,22 +25,6 @@ pub fn read_file<P: AsRef<Path> + Debug + Copy>(path: P) -> String {
     }
 }
 
-pub fn read_file_bytes<P: AsRef<Path> + Debug + Copy>(path: P) -> Vec<u8> {
-    match open_file(path) {
-        Ok(mut file) => {
-            let file_size = file.metadata().expect("Unable to get file metadata").len() as usize;
-            let mut file_content = Vec::with_capacity(file_size);
-            file.read_to_end(&mut file_content).expect("Error reading file");
-
-            file_content
-        },
-        Err(e) => {
-            println!("Unable to open file at path: {:?}\nError: {}", path, e);
-            return vec![]
-        },
-    }
-}
-
 pub fn get_watched_cartridges(path: &str) -> Vec<String> {
     let mut cartridges = vec![];
     for entry in read_dir(path).unwrap() {
@@ -0,0 +1,97 @@
+use std::fs;
+use std::collections::HashSet;
+
+use serde::{Serialize, Deserialize};
+
+use super::data::{Data, Operation};
+
+/// Ordered operations of a batch that can be saved to disk and replayed later
+/// Uploads are replayed with the current content of the local files
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChangeSet {
+    pub base_path: String,
+    pub stages: Vec<Vec<Operation>>,
+}
+
+impl ChangeSet {
+    pub fn new(data: &Data) -> Self {
+        ChangeSet {
+            base_path: data.get_base_path().to_owned(),
+            stages: data.operations(),
+        }
+    }
+
+    /// Change set without operations
+    pub fn empty(base_path: &str) -> Self {
+        ChangeSet {
+            base_path: base_path.to_owned(),
+            stages: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.iter().all(|stage| stage.is_empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len()).sum()
+    }
+
+    /// The change set without the failed operations, e.g. to record what reached the Sandbox
+    pub fn without(&self, failed: &Vec<(Operation, String)>) -> Self {
+        let failed: HashSet<&Operation> = failed.iter().map(|(operation, _)| operation).collect();
+
+        ChangeSet {
+            base_path: self.base_path.clone(),
+            stages: self.stages.iter()
+                .map(|stage| stage.iter().filter(|operation| !failed.contains(operation)).cloned().collect())
+                .collect(),
+        }
+    }
+
+    /// Points the uploads to the local files under another base path
+    /// e.g. replaying a teammate's change set against a different checkout
+    pub fn rebase(&mut self, base_path: &str) -> () {
+        for stage in self.stages.iter_mut() {
+            for operation in stage.iter_mut() {
+                if let Operation::Upload(file) = operation {
+                    file.full_path = format!("{}{}", base_path, file.rel_path);
+                }
+            }
+        }
+
+        self.base_path = base_path.to_owned();
+    }
+
+    /// Human readable list of the operations, used for dry runs
+    pub fn describe(&self) -> Vec<String> {
+        self.stages.iter().enumerate()
+            .flat_map(|(index, stage)| {
+                stage.iter().map(move |operation| match operation {
+                    Operation::Rename(rename) => format!("[{}] [R] from: {} to: {}", index + 1, rename.current, rename.new),
+                    Operation::Upload(file) => format!("[{}] [U] {}", index + 1, file.rel_path),
+                    Operation::Remove(path) => format!("[{}] [D] {}", index + 1, path),
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Unable to serialize change set: {}", e))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Unable to parse change set: {}", e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_json()?).map_err(|e| format!("Unable to write change set at path: {}\nError: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => ChangeSet::from_json(&json),
+            Err(e) => Err(format!("Unable to read change set at path: {}\nError: {}", path, e)),
+        }
+    }
+}
@@ -1,11 +1,20 @@
-use notify::DebouncedEvent;
-
 use super::data;
+use super::super::filter::Filter;
+use super::super::event::FileEvent;
+use super::super::manifest::Manifest;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 pub struct Collection {
     pub data: data::Data,
     cartridges: Vec<String>,
+    /// Cartridges removed since the last data, kept until their removal is taken
+    removed_cartridges: Vec<String>,
     ignore_list: Vec<String>,
+    rescan: bool,
+    synced: Option<Arc<Mutex<Manifest>>>,
+    temp_files: Filter,
 }
 
 impl Collection {
@@ -13,46 +22,132 @@ impl Collection {
         Collection {
             data: data::Data::new(base_path),
             cartridges: cartridges,
+            removed_cartridges: vec![],
             ignore_list: ignore_list,
+            rescan: false,
+            synced: None,
+            temp_files: Filter::editor_temp_files(),
+        }
+    }
+
+    /// Includes a new cartridge in the collected data
+    pub fn add_cartridge(&mut self, name: String) -> () {
+        self.removed_cartridges.retain(|removed| removed != &name);
+        if !self.cartridges.contains(&name) {
+            self.cartridges.push(name);
+        }
+    }
+
+    /// Stops collecting a cartridge whose folder was removed
+    /// Its removal is still part of the next data so the Sandbox copy is removed as well
+    pub fn remove_cartridge(&mut self, name: &str) -> () {
+        if self.cartridges.iter().any(|cartridge| cartridge == name) {
+            self.cartridges.retain(|cartridge| cartridge != name);
+            self.removed_cartridges.push(name.to_owned());
         }
     }
 
-    pub fn parse_event(&mut self, event: DebouncedEvent) -> () {
+    pub fn get_cartridges(&self) -> &Vec<String> {
+        &self.cartridges
+    }
+
+    /// Files known to be on the Sandbox, shared with the thread recording the pushed operations
+    /// Without them every rename is pushed as a rename of the remote file or folder
+    pub fn set_synced(&mut self, synced: Arc<Mutex<Manifest>>) -> () {
+        self.synced = Some(synced);
+    }
+
+    pub fn get_synced(&self) -> Option<&Arc<Mutex<Manifest>>> {
+        self.synced.as_ref()
+    }
+
+    /// Checks the path against the synced files, None when they are unknown
+    fn is_synced(&self, path: &Path) -> Option<bool> {
+        self.synced.as_ref().map(|synced| synced.lock().unwrap().contains(&self.data.rel_path(path)))
+    }
+
+    fn is_temp_file(&self, path: &Path) -> bool {
+        self.temp_files.matches_path(&path.to_string_lossy())
+    }
+
+    /// Returns true once if events were lost since the last call
+    pub fn take_rescan(&mut self) -> bool {
+        let rescan = self.rescan;
+        self.rescan = false;
+
+        rescan
+    }
+
+    pub fn parse_event(&mut self, event: FileEvent) -> () {
 
         match event {
-            DebouncedEvent::Create(path) => {
+            // editors save by writing a temp file and renaming it over the original
+            FileEvent::Rename(path, new_path) => self.parse_rename(path, new_path),
+            FileEvent::Create(path) | FileEvent::Write(path) | FileEvent::Remove(path) if self.is_temp_file(&path) => (),
+            FileEvent::Create(path) => {
                 // check only for files
                 self.data.push_create(path);
             },
-            DebouncedEvent::Write(path) => {
+            FileEvent::Write(path) => {
                 // check only the files
                 self.data.push_write(path);
             },
-            DebouncedEvent::Remove(path) => {
+            FileEvent::Remove(path) => {
                 // check only the files and folders group by folder
                 // check if folder exists and remove all the files that are part of this folder
                 self.data.push_remove(path);
             },
-            DebouncedEvent::Rename(path, new_path) => {
-                // check only files and folders
-                self.data.push_rename(path, new_path);
+            // permissions are not synced to the Sandbox
+            FileEvent::Chmod(_) => (),
+            // events were lost, the local files have to be compared with the synced state
+            FileEvent::Rescan => self.rescan = true,
+            FileEvent::Error(message, path) => {
+                println!("Watcher error: {} {:?}", message, path);
+                self.rescan = true;
             },
-            _ => (),
+        }
+    }
+
+    fn parse_rename(&mut self, path: PathBuf, new_path: PathBuf) -> () {
+        if self.is_temp_file(&path) && self.is_temp_file(&new_path) {
+            return;
+        }
+
+        if self.is_temp_file(&new_path) {
+            // the original is moved away to a backup (vim) and written again
+            self.data.push_remove(path);
+        } else if self.is_temp_file(&path) || self.is_synced(&path) == Some(false) {
+            // the source never reached the Sandbox so there is nothing to move remotely
+            self.data.push_replace(path, new_path);
+        } else if self.is_synced(&new_path) == Some(true) {
+            // the target is overwritten
+            self.data.push_remove(path);
+            self.data.push_write(new_path);
+        } else {
+            // check only files and folders
+            self.data.push_rename(path, new_path);
         }
     }
 
     // returns current available files and folders to be removed, added, update
     // sorts and updates the remove files and folders before returning them
-    pub fn get_data(&mut self) -> data::Data {
+    // the filter is applied on top of the cartridges and the ignore list
+    pub fn get_data(&mut self, filter: &Filter) -> data::Data {
         self.data.update();
 
         //filter only the files that are part of the "cartridges"
-        self.data.filter(&self.cartridges, "include");
+        // whole folders are compared, a path merely containing a cartridge name is not part of it
+        let mut cartridges = self.cartridges.clone();
+        cartridges.extend(self.removed_cartridges.drain(..));
+        self.data.filter(&Filter::under(&cartridges));
 
         if !self.ignore_list.is_empty() {
-            self.data.filter(&self.ignore_list, "exclude");
+            self.data.filter(&Filter::exclude(&self.ignore_list));
         }
 
+        self.data.filter(&self.temp_files.clone().not());
+        self.data.filter(filter);
+
         let data: data::Data = self.data.clone();
         self.data.reset();
 
@@ -1,23 +1,77 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::collections::{HashSet, HashMap, BTreeSet};
+use std::cmp;
 
-#[derive(Debug, Clone)]
+use serde::{Serialize, Deserialize};
+
+use super::tree::PathTree;
+use super::super::filter::{Filter, Entry};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct File {
     pub full_path: String,
     pub rel_path: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Rename {
     pub current: String,
     pub new: String,
 }
 
-#[derive(Debug, Clone)]
+/// Single remote operation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Rename(Rename),
+    Upload(File),
+    Remove(String),
+}
+
+impl Operation {
+    /// Paths touched by the operation
+    fn paths(&self) -> Vec<&str> {
+        match self {
+            Operation::Rename(rename) => vec![rename.current.as_str(), rename.new.as_str()],
+            Operation::Upload(file) => vec![file.rel_path.as_str()],
+            Operation::Remove(path) => vec![path.as_str()],
+        }
+    }
+
+    /// Checks if this operation has to run before the other one
+    /// Relies on the events being coalesced when they are pushed
+    /// e.g. an upload inside a removed folder always happened after the remove
+    fn precedes(&self, other: &Operation) -> bool {
+        match (self, other) {
+            // a remove and then a rename into the removed folder, otherwise the rename came first
+            (Operation::Remove(path), Operation::Rename(rename)) => is_inside(&rename.new, path),
+            (Operation::Rename(rename), Operation::Remove(path)) => !is_inside(&rename.new, path) && is_related(self, other),
+            (Operation::Remove(_), Operation::Upload(_)) => is_related(self, other),
+            (Operation::Rename(_), Operation::Upload(_)) => is_related(self, other),
+            _ => false,
+        }
+    }
+}
+
+/// Sorted sets of the queued uploads and removes, so pushing an event does not scan the whole batch
+/// Rebuilt from the lists after they were replaced as a whole (see Data::index)
+#[derive(Debug, Clone, Default)]
+struct Index {
+    uploads: BTreeSet<String>,
+    removes: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Data {
-    pub upload: Option<Vec<File>>,
-    pub rename: Option<Vec<Rename>>,
-    pub remove: Option<Vec<String>>,
+    upload: Option<Vec<File>>,
+    rename: Option<Vec<Rename>>,
+    remove: Option<Vec<String>>,
+    #[serde(default)]
+    created: BTreeSet<String>,
     base_path: String,
+    /// None until the next push needs it, e.g. after loading, filter or update
+    #[serde(skip)]
+    index: Option<Index>,
 }
 
 impl Data {
@@ -26,20 +80,75 @@ impl Data {
             upload: None,
             rename: None,
             remove: None,
-            base_path: base_path.to_owned()
+            created: BTreeSet::new(),
+            base_path: base_path.to_owned(),
+            index: None,
         }
     }
 
+    pub fn uploads(&self) -> &[File] {
+        self.upload.as_deref().unwrap_or(&[])
+    }
+
+    // the pushes only need the uploads and removes, the tests check the renames as well
+    #[cfg(test)]
+    pub fn renames(&self) -> &[Rename] {
+        self.rename.as_deref().unwrap_or(&[])
+    }
+
+    pub fn removes(&self) -> &[String] {
+        self.remove.as_deref().unwrap_or(&[])
+    }
+
+    /// Replaces the paths to remove, e.g. a list built by the tests or the benchmarks
+    #[cfg(any(test, feature = "bench"))]
+    pub fn set_removes(&mut self, paths: Vec<String>) {
+        self.remove = Some(paths);
+        self.index = None;
+    }
+
+    /// Keeps only the uploads the predicate accepts
+    pub fn retain_uploads<F: FnMut(&File) -> bool>(&mut self, keep: F) {
+        if let Some(files) = self.upload.as_mut() {
+            files.retain(keep);
+            self.index = None;
+        }
+    }
+
+    /// Number of collected operations
+    pub fn len(&self) -> usize {
+        self.upload.as_ref().map(|files| files.len()).unwrap_or(0)
+            + self.rename.as_ref().map(|renames| renames.len()).unwrap_or(0)
+            + self.remove.as_ref().map(|paths| paths.len()).unwrap_or(0)
+    }
+
+    pub fn get_base_path(&self) -> &str {
+        &self.base_path
+    }
+
     pub fn reset(&mut self) -> () {
         self.upload = None;
         self.rename = None;
         self.remove = None;
+        self.created = BTreeSet::new();
+        self.index = None;
     }
 
     /// Files and Folders are created
     /// Push only files
+    /// A file that did not exist before this batch never needs a remote delete
     pub fn push_create(&mut self, path: PathBuf) -> () {
-        self.push_write(path);
+        let string_path: String = self.get_string_path(path);
+
+        if self.is_file(&string_path) {
+            let rel_path: String = self.get_relative_path(&string_path);
+
+            if !self.is_removed(&rel_path) {
+                self.created.insert(rel_path);
+            }
+
+            self.push_write_path(&string_path);
+        }
     }
 
     /// Push files with full and relative paths
@@ -47,7 +156,7 @@ impl Data {
         let string_path: String = self.get_string_path(path);
 
         if self.is_file(&string_path) {
-            self.push_file(&string_path);
+            self.push_write_path(&string_path);
         }
     }
 
@@ -56,18 +165,93 @@ impl Data {
     pub fn push_remove(&mut self, path: PathBuf) -> () {
         let rel_path: String = self.get_relative_path(&self.get_string_path(path));
 
-        self.remove = match self.remove.take() {
-            Some(mut vec) => {
-                vec.push(rel_path);
-                Some(vec)
-            },
-            None => Some(vec![rel_path]),
-        };
+        // pending uploads are pointless once the file or folder is gone
+        self.drop_uploads_inside(&rel_path);
+
+        // a file created and removed in the same batch never reached the remote
+        let was_created: bool = self.created.contains(&rel_path);
+        remove_inside(&mut self.created, &rel_path);
+
+        // renamed into the removed path, the source has to be removed instead
+        let mut sources: Vec<String> = vec![];
+        self.rename = self.rename.take().map(|renames| {
+            renames.into_iter().filter(|rename| {
+                if is_inside(&rename.new, &rel_path) {
+                    sources.push(rename.current.clone());
+                    false
+                } else {
+                    true
+                }
+            }).collect()
+        });
+
+        for source in sources.into_iter() {
+            self.push_remove_path(source);
+        }
+
+        if !was_created {
+            self.push_remove_path(rel_path);
+        }
     }
 
     pub fn push_rename(&mut self, path: PathBuf, new_path: PathBuf) -> () {
         let current_name: String = self.get_relative_path(&self.get_string_path(path));
-        let new_name: String = self.get_relative_path(&self.get_string_path(new_path));
+        let new_string_path: String = self.get_string_path(new_path);
+        let new_name: String = self.get_relative_path(&new_string_path);
+
+        // the source was reported removed as well (e.g. a checkout seen by the watcher and by git), the move removes it
+        if self.index().removes.remove(&current_name) {
+            self.remove = self.remove.take().map(|paths| paths.into_iter().filter(|path| path != &current_name).collect());
+        }
+
+        // the target is overwritten by the rename
+        if has_inside(&self.index().removes, &new_name) {
+            self.remove = self.remove.take().map(|paths| paths.into_iter().filter(|path| !is_inside(path, &new_name)).collect());
+            remove_inside(&mut self.index().removes, &new_name);
+        }
+        if self.index().uploads.remove(&new_name) {
+            self.upload = self.upload.take().map(|files| files.into_iter().filter(|file| file.rel_path != new_name).collect());
+        }
+        self.created.remove(&new_name);
+
+        // pending uploads follow the renamed file or folder
+        if has_inside(&self.index().uploads, &current_name) {
+            let base_path: String = self.base_path.clone();
+            self.upload = self.upload.take().map(|files| {
+                files.into_iter().map(|file| {
+                    match replace_prefix(&file.rel_path, &current_name, &new_name) {
+                        Some(rel_path) => File {
+                            full_path: format!("{}{}", base_path, rel_path),
+                            rel_path: rel_path,
+                        },
+                        None => file,
+                    }
+                }).collect()
+            });
+            move_inside(&mut self.index().uploads, &current_name, &new_name);
+        }
+
+        // a file created in this batch is uploaded under the new name instead
+        if self.created.remove(&current_name) {
+            self.created.insert(new_name);
+            self.push_write_path(&new_string_path);
+            return;
+        }
+
+        move_inside(&mut self.created, &current_name, &new_name);
+
+        // chain consecutive renames of the same path
+        if let Some(renames) = self.rename.as_mut() {
+            if let Some(index) = renames.iter().position(|rename| rename.new == current_name) {
+                if renames[index].current == new_name {
+                    renames.remove(index);
+                } else {
+                    renames[index].new = new_name;
+                }
+                return;
+            }
+        }
+
         let rename_collection: Rename = Rename {
             current: current_name,
             new: new_name,
@@ -82,6 +266,163 @@ impl Data {
         }
     }
 
+    /// The source never reached the remote (e.g. the temp file of an atomic save)
+    /// so the target is written instead of renaming the source
+    pub fn push_replace(&mut self, path: PathBuf, new_path: PathBuf) -> () {
+        let current_name: String = self.get_relative_path(&self.get_string_path(path));
+
+        self.drop_uploads_inside(&current_name);
+        remove_inside(&mut self.created, &current_name);
+
+        self.push_write(new_path);
+    }
+
+    /// Path relative to the base path in the form used by the collected data
+    pub fn rel_path(&self, path: &Path) -> String {
+        self.get_relative_path(&self.get_string_path(path.to_path_buf()))
+    }
+
+    /// Written files are uploaded only once and are no longer removed
+    fn push_write_path(&mut self, string_path: &str) -> () {
+        let rel_path: String = self.get_relative_path(string_path);
+
+        if self.index().removes.remove(&rel_path) {
+            self.remove = self.remove.take().map(|paths| paths.into_iter().filter(|path| path != &rel_path).collect());
+        }
+
+        if !self.index().uploads.contains(&rel_path) {
+            self.push_file(string_path);
+        }
+    }
+
+    fn drop_uploads_inside(&mut self, rel_path: &str) -> () {
+        if has_inside(&self.index().uploads, rel_path) {
+            self.upload = self.upload.take().map(|files| {
+                files.into_iter().filter(|file| !is_inside(&file.rel_path, rel_path)).collect()
+            });
+            remove_inside(&mut self.index().uploads, rel_path);
+        }
+    }
+
+    /// Paths already queued for removal are not added again
+    fn push_remove_path(&mut self, rel_path: String) -> () {
+        if !self.index().removes.insert(rel_path.clone()) {
+            return;
+        }
+
+        self.remove = match self.remove.take() {
+            Some(mut vec) => {
+                vec.push(rel_path);
+                Some(vec)
+            },
+            None => Some(vec![rel_path]),
+        };
+    }
+
+    fn is_removed(&mut self, rel_path: &str) -> bool {
+        self.index().removes.contains(rel_path)
+    }
+
+    /// The index of the queued paths, built from the lists when they were replaced since the last push
+    fn index(&mut self) -> &mut Index {
+        if self.index.is_none() {
+            self.index = Some(Index {
+                uploads: self.upload.iter().flatten().map(|file| file.rel_path.clone()).collect(),
+                removes: self.remove.iter().flatten().cloned().collect(),
+            });
+        }
+
+        self.index.as_mut().unwrap()
+    }
+
+    /// Returns the operations grouped in stages
+    /// Every stage depends only on the previous ones so the operations inside a stage can run in any order
+    pub fn operations(&self) -> Vec<Vec<Operation>> {
+        let mut operations: Vec<Operation> = vec![];
+        operations.extend(self.rename.clone().unwrap_or(vec![]).into_iter().map(Operation::Rename));
+        operations.extend(self.remove.clone().unwrap_or(vec![]).into_iter().map(Operation::Remove));
+        operations.extend(self.upload.clone().unwrap_or(vec![]).into_iter().map(Operation::Upload));
+
+        // number of operations each operation still waits for
+        let mut dependencies: Vec<usize> = vec![0; operations.len()];
+        let mut dependents: Vec<Vec<usize>> = vec![vec![]; operations.len()];
+
+        // only operations on the same path or on folders containing each other can depend on each other
+        let mut by_path: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, operation) in operations.iter().enumerate() {
+            for path in operation.paths().into_iter() {
+                by_path.entry(path.trim_end_matches("/")).or_insert(vec![]).push(i);
+            }
+        }
+
+        let mut related: BTreeSet<(usize, usize)> = BTreeSet::new();
+        for (j, operation) in operations.iter().enumerate() {
+            for path in operation.paths().into_iter() {
+                for folder in ancestors(path).into_iter() {
+                    for &i in by_path.get(folder).map(|indexes| indexes.as_slice()).unwrap_or(&[]) {
+                        if i != j {
+                            related.insert((cmp::min(i, j), cmp::max(i, j)));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, j) in related.into_iter() {
+            let (first, second) = (&operations[i], &operations[j]);
+
+            match (first, second) {
+                // renames touching the same paths keep the order they happened in
+                (Operation::Rename(_), Operation::Rename(_)) => {
+                    dependencies[j] += 1;
+                    dependents[i].push(j);
+                },
+                _ => {
+                    if first.precedes(second) {
+                        dependencies[j] += 1;
+                        dependents[i].push(j);
+                    }
+                    if second.precedes(first) {
+                        dependencies[i] += 1;
+                        dependents[j].push(i);
+                    }
+                },
+            }
+        }
+
+        let mut stages: Vec<Vec<Operation>> = vec![];
+        let mut ready: Vec<usize> = (0..operations.len()).filter(|&i| dependencies[i] == 0).collect();
+
+        while !ready.is_empty() {
+            let mut next: Vec<usize> = vec![];
+
+            for &i in ready.iter() {
+                for &j in dependents[i].iter() {
+                    dependencies[j] -= 1;
+                    if dependencies[j] == 0 {
+                        next.push(j);
+                    }
+                }
+            }
+
+            stages.push(ready.iter().map(|&i| operations[i].clone()).collect());
+            next.sort();
+            ready = next;
+        }
+
+        // operations waiting on each other keep the default order in a last stage
+        let remaining: Vec<Operation> = (0..operations.len())
+            .filter(|&i| dependencies[i] > 0)
+            .map(|i| operations[i].clone())
+            .collect();
+
+        if !remaining.is_empty() {
+            stages.push(remaining);
+        }
+
+        stages
+    }
+
     /// Basic check if the path is a file or folder
     /// if the last part after the slahs contains any dot in it then it should be a file
     fn is_file(&self, path: &str) -> bool {
@@ -106,6 +447,7 @@ impl Data {
             full_path: string_path.to_owned(),
             rel_path: self.get_relative_path(string_path)
         };
+        self.index().uploads.insert(file_path.rel_path.clone());
 
         self.upload = match self.upload.take() {
             Some(mut vec) => {
@@ -138,94 +480,127 @@ impl Data {
         // if all the files from a folder are deleted prefer deleting the folder instead of individual files
         if self.remove.is_some() {
             let (mut folders, files) = self.split_folders_files();
-            let mut filtered_folders = vec![];
-            let mut final_remove;
+            let mut tree = PathTree::new();
+            let mut final_remove = vec![];
 
-            folders.sort_by(|folder_a, folder_b| {
-                let count_a: usize = folder_a.split("/").count();
-                let count_b: usize = folder_b.split("/").count();
+            // folders are sorted by depth so every ancestor is in the tree before its children
+            folders.sort_by_key(|folder| folder.split("/").filter(|component| !component.is_empty()).count());
 
-                count_a.cmp(&count_b)
-            });
-
-            match folders.get(0) {
-                Some(folder) => filtered_folders.push(folder.clone()),
-                None => (),
-            }
-
-            for folder in folders.iter() {
-                if !filtered_folders.contains(folder) {
-                    let mut push = true;
-                    for fill_folder in filtered_folders.clone().into_iter() {
-                        if folder.contains(&fill_folder) || fill_folder.contains(folder) {
-                            push = false;
-                            break;
-                        }
-                    }
-
-                    if push {
-                        filtered_folders.push(folder.to_string());
-                    }
+            for folder in folders.into_iter() {
+                if !tree.covers(&folder) {
+                    tree.insert(&folder);
+                    final_remove.push(folder);
                 }
             }
 
-            final_remove = filtered_folders.clone();
+            let mut seen_files: HashSet<String> = HashSet::new();
 
-            for file in files.iter() {
-                let mut push = true;
-                for folder in filtered_folders.iter() {
-                    if file.contains(folder) {
-                        push = false;
-                        break;
-                    }
-                }
-
-                if push {
-                    final_remove.push(file.to_owned());
+            for file in files.into_iter() {
+                if !tree.covers(&file) && seen_files.insert(file.clone()) {
+                    final_remove.push(file);
                 }
             }
 
             self.remove = Some(final_remove);
+            self.index = None;
         }
     }
 
-    /// Filter data
-    ///
-    pub fn filter(&mut self, paths: &Vec<String>, filter_type: &str) -> () {
+    /// Keeps only the files and folders matching the filter
+    pub fn filter(&mut self, filter: &Filter) -> () {
         if self.upload.is_some() {
             self.upload = self.upload.take().map(|files| {
                 files.into_iter()
-                    .filter(|file| {
-                        match filter_type {
-                            "exclude" => paths.into_iter().all(|path| !file.rel_path.contains(path)),
-                            _ => paths.into_iter().any(|path| file.rel_path.contains(path)),
-                        }
-                    }).collect::<Vec<File>>()
+                    .filter(|file| filter.matches(&Entry::new(&file.rel_path, Some(Path::new(&file.full_path)))))
+                    .collect::<Vec<File>>()
             });
         }
 
         if self.remove.is_some() {
             self.remove = self.remove.take().map(|collections| {
                 collections.into_iter()
-                    .filter(|remove| {
-                        match filter_type {
-                            "exclude" => paths.into_iter().all(|path| !remove.contains(path)),
-                            _ => paths.into_iter().any(|path| remove.contains(path)),
-                        }
-                    }).collect::<Vec<String>>()
+                    .filter(|remove| filter.matches_path(remove))
+                    .collect::<Vec<String>>()
             });
         }
 
         if self.rename.is_some() {
             self.rename = self.rename.take().map(|collections| {
                 collections.into_iter()
-                    .filter(|rename| {
-                        match filter_type {
-                            "exclude" => paths.into_iter().all(|path| !rename.current.contains(path)),
-                            _ => paths.into_iter().any(|path| rename.current.contains(path)),
-                        }
-                    }).collect::<Vec<Rename>>()
+                    .filter(|rename| filter.matches_path(&rename.current))
+                    .collect::<Vec<Rename>>()
             });
         }
+
+        self.index = None;
+    }
+}
+
+/// Checks if the path is the folder itself or any of its children
+/// Compares whole path components so "/js" does not contain "/jsx/file.js"
+pub fn is_inside(path: &str, folder: &str) -> bool {
+    let folder = folder.trim_end_matches("/");
+
+    path == folder || (path.starts_with(folder) && path[folder.len()..].starts_with("/"))
+}
+
+/// Checks if the operations touch the same file or folder
+fn is_related(first: &Operation, second: &Operation) -> bool {
+    first.paths().iter().any(|a| {
+        second.paths().iter().any(|b| is_inside(a, b) || is_inside(b, a))
+    })
+}
+
+/// Checks if the sorted set contains the folder or any path inside it
+fn has_inside(set: &BTreeSet<String>, folder: &str) -> bool {
+    let folder = folder.trim_end_matches("/");
+
+    set.contains(folder) || set.range(format!("{}/", folder)..)
+        .next()
+        .map(|path| is_inside(path, folder))
+        .unwrap_or(false)
+}
+
+/// Removes the folder and every path inside it from the sorted set
+fn remove_inside(set: &mut BTreeSet<String>, folder: &str) -> Vec<String> {
+    let folder = folder.trim_end_matches("/");
+    let mut removed: Vec<String> = set.range(format!("{}/", folder)..)
+        .take_while(|path| is_inside(path, folder))
+        .cloned()
+        .collect();
+
+    for path in removed.iter() {
+        set.remove(path);
+    }
+    if set.remove(folder) {
+        removed.push(folder.to_owned());
+    }
+
+    removed
+}
+
+/// Moves the folder and every path inside it to the new folder
+fn move_inside(set: &mut BTreeSet<String>, from: &str, to: &str) -> () {
+    for path in remove_inside(set, from).into_iter() {
+        set.insert(replace_prefix(&path, from, to).unwrap_or(path));
+    }
+}
+
+/// The path followed by the folders containing it, e.g. /app/js/file.js, /app/js, /app
+fn ancestors(path: &str) -> Vec<&str> {
+    let path = path.trim_end_matches("/");
+    let mut ancestors: Vec<&str> = vec![path];
+
+    ancestors.extend(path.rmatch_indices("/").map(|(index, _)| &path[..index]).filter(|folder| !folder.is_empty()));
+
+    ancestors
+}
+
+/// Moves the path from one folder to another if it is inside the first one
+fn replace_prefix(path: &str, from: &str, to: &str) -> Option<String> {
+    if is_inside(path, from) {
+        Some(format!("{}{}", to.trim_end_matches("/"), &path[from.trim_end_matches("/").len()..]))
+    } else {
+        None
     }
 }
\ No newline at end of file
@@ -1,7 +1,14 @@
 mod data;
 mod collection;
+mod tree;
+mod change_set;
 pub use data::Data as Data;
+pub use data::Operation as Operation;
+pub use data::File as File;
+pub use data::Rename as Rename;
+pub(crate) use data::is_inside;
 pub use collection::Collection as Collection;
+pub use change_set::ChangeSet as ChangeSet;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
@@ -11,7 +11,7 @@ fn split_folders_files() {
     vec.push("/folder/folder_2".to_owned());
     vec.push("/folder/folder_test/folder_3/some_file_2.txt".to_owned());
 
-    some_data.remove = Some(vec);;
+    some_data.set_removes(vec);;
 
     let mut test_data_folders = vec![];
     test_data_folders.push("/folder/folder_2/folder_3".to_owned());
@@ -85,7 +85,7 @@ fn filter_remove() {
 
     let vec: Vec<String> = temp_vec.into_iter().map(|item| item.to_string()).collect();
 
-    some_data.remove = Some(vec);
+    some_data.set_removes(vec);
 
     some_data.update();
 
@@ -93,5 +93,461 @@ fn filter_remove() {
     test_data.push("/app_canada_layer/cartridge/templates/handlebars_compiled".to_owned());
     test_data.push("/app_canada_layer/cartridge/static/default/dist".to_owned());
 
-    assert_eq!(some_data.remove.unwrap(), test_data);
-}
\ No newline at end of file
+    assert_eq!(some_data.removes(), test_data);
+}
+
+#[test]
+fn coalesce_created_then_removed() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_create(PathBuf::from("/base/app/file.js"));
+    some_data.push_write(PathBuf::from("/base/app/file.js"));
+    some_data.push_remove(PathBuf::from("/base/app/file.js"));
+
+    assert_eq!(some_data.uploads().len(), 0);
+    assert_eq!(some_data.removes().len(), 0);
+}
+
+#[test]
+fn coalesce_repeated_writes() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    for _ in 0..5 {
+        some_data.push_write(PathBuf::from("/base/app/file.js"));
+    }
+
+    let upload = some_data.uploads();
+    assert_eq!(upload.len(), 1);
+    assert_eq!(upload[0].rel_path, "/app/file.js");
+}
+
+#[test]
+fn coalesce_written_then_removed() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_write(PathBuf::from("/base/app/file.js"));
+    some_data.push_remove(PathBuf::from("/base/app/file.js"));
+    some_data.push_write(PathBuf::from("/base/app/other.js"));
+    some_data.push_remove(PathBuf::from("/base/app/other.js"));
+    some_data.push_create(PathBuf::from("/base/app/other.js"));
+
+    assert_eq!(some_data.uploads()[0].rel_path, "/app/other.js");
+    assert_eq!(some_data.removes(), vec!["/app/file.js".to_owned()]);
+}
+
+#[test]
+fn coalesce_renames() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_write(PathBuf::from("/base/app/a.js"));
+    some_data.push_rename(PathBuf::from("/base/app/a.js"), PathBuf::from("/base/app/b.js"));
+    some_data.push_rename(PathBuf::from("/base/app/b.js"), PathBuf::from("/base/app/c.js"));
+    some_data.push_create(PathBuf::from("/base/app/tmp.js"));
+    some_data.push_rename(PathBuf::from("/base/app/tmp.js"), PathBuf::from("/base/app/d.js"));
+
+    let rename = some_data.renames();
+    assert_eq!(rename.len(), 1);
+    assert_eq!((rename[0].current.as_str(), rename[0].new.as_str()), ("/app/a.js", "/app/c.js"));
+
+    let upload: Vec<String> = some_data.uploads().iter().map(|file| file.full_path.clone()).collect();
+    assert_eq!(upload, vec!["/base/app/c.js".to_owned(), "/base/app/d.js".to_owned()]);
+}
+
+#[test]
+fn operations_keep_causal_order() {
+    use std::path::PathBuf;
+    use super::data::{Data, Operation};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_remove(PathBuf::from("/base/app/dist"));
+    some_data.push_create(PathBuf::from("/base/app/dist/main.js"));
+    some_data.push_rename(PathBuf::from("/base/app/a.js"), PathBuf::from("/base/app/b.js"));
+    some_data.push_create(PathBuf::from("/base/app/a.js"));
+
+    let stages: Vec<Vec<String>> = some_data.operations().into_iter()
+        .map(|stage| stage.into_iter().map(|operation| match operation {
+            Operation::Rename(rename) => format!("R {}", rename.current),
+            Operation::Upload(file) => format!("U {}", file.rel_path),
+            Operation::Remove(path) => format!("D {}", path),
+        }).collect())
+        .collect();
+
+    assert_eq!(stages, vec![
+        vec!["R /app/a.js".to_owned(), "D /app/dist".to_owned()],
+        vec!["U /app/dist/main.js".to_owned(), "U /app/a.js".to_owned()],
+    ]);
+}
+
+#[test]
+fn operations_depend_on_related_paths_only() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_remove(PathBuf::from("/base/app/js"));
+    some_data.push_create(PathBuf::from("/base/app/jsx/file.js"));
+    some_data.push_create(PathBuf::from("/base/app/js/deep/file.js"));
+    some_data.push_rename(PathBuf::from("/base/app/a.js"), PathBuf::from("/base/app/js/a.js"));
+
+    let stages: Vec<usize> = some_data.operations().iter().map(|stage| stage.len()).collect();
+
+    // the rename and the upload into the removed folder wait for the remove, /app/jsx does not
+    assert_eq!(stages, vec![2, 2]);
+}
+
+#[test]
+fn pushes_follow_lists_changed_directly() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_write(PathBuf::from("/base/app/a.js"));
+    some_data.set_removes(vec!["/app/b.js".to_owned(), "/app/dist".to_owned()]);
+
+    // a reloaded batch is indexed again before the next event
+    let mut some_data: Data = serde_json::from_str(&serde_json::to_string(&some_data).unwrap()).unwrap();
+    some_data.push_write(PathBuf::from("/base/app/a.js"));
+    some_data.push_write(PathBuf::from("/base/app/b.js"));
+    some_data.push_remove(PathBuf::from("/base/app/dist"));
+
+    let upload: Vec<String> = some_data.uploads().iter().map(|file| file.rel_path.clone()).collect();
+    assert_eq!(upload, vec!["/app/a.js".to_owned(), "/app/b.js".to_owned()]);
+    assert_eq!(some_data.removes(), vec!["/app/dist".to_owned()]);
+}
+
+#[test]
+fn pushes_follow_lists_replaced_with_the_same_length() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+
+    let mut some_data = Data::new("/base");
+    some_data.set_removes(vec!["/app/a.js".to_owned()]);
+    some_data.push_remove(PathBuf::from("/base/app/a.js"));
+
+    some_data.set_removes(vec!["/app/b.js".to_owned()]);
+    some_data.push_remove(PathBuf::from("/base/app/b.js"));
+    assert_eq!(some_data.removes(), vec!["/app/b.js".to_owned()]);
+
+    some_data.push_write(PathBuf::from("/base/app/c.js"));
+    some_data.retain_uploads(|file| file.rel_path != "/app/c.js");
+    some_data.push_write(PathBuf::from("/base/app/c.js"));
+    assert_eq!(some_data.uploads().len(), 1);
+}
+
+#[test]
+fn update_matches_whole_components() {
+    use super::data::{Data};
+
+    let mut some_data = Data::new("");
+    some_data.set_removes(vec![
+        "/app/cartridge/js".to_owned(),
+        "/app/cartridge/jsx/file.js".to_owned(),
+        "/app/cartridge/js/file.js".to_owned(),
+        "/other/dist/app/cartridge/js".to_owned(),
+    ]);
+
+    some_data.update();
+
+    assert_eq!(some_data.removes(), vec![
+        "/app/cartridge/js".to_owned(),
+        "/other/dist/app/cartridge/js".to_owned(),
+        "/app/cartridge/jsx/file.js".to_owned(),
+    ]);
+}
+
+mod remove_properties {
+    use std::path::Path;
+    use proptest::prelude::*;
+    use super::super::data::{Data};
+
+    // names sharing prefixes make textual matching fail
+    fn path_strategy() -> impl Strategy<Value = String> {
+        let segment = prop::sample::select(vec!["js", "jsx", "dist", "distx", "a", "a.js", "b.css"]);
+
+        prop::collection::vec(segment, 1..5)
+            .prop_map(|segments| format!("/{}", segments.join("/")))
+    }
+
+    fn updated(paths: &Vec<String>) -> Vec<String> {
+        let mut some_data = Data::new("");
+        some_data.set_removes(paths.clone());
+        some_data.update();
+
+        some_data.removes().to_vec()
+    }
+
+    fn is_folder(path: &str) -> bool {
+        path.split("/").last().unwrap().split(".").count() == 1
+    }
+
+    proptest! {
+        #[test]
+        fn every_path_is_still_removed(paths in prop::collection::vec(path_strategy(), 0..40)) {
+            let remove = updated(&paths);
+
+            for path in paths.iter() {
+                prop_assert!(remove.iter().any(|kept| Path::new(path).starts_with(kept)));
+            }
+        }
+
+        #[test]
+        fn only_requested_paths_are_removed(paths in prop::collection::vec(path_strategy(), 0..40)) {
+            for kept in updated(&paths).iter() {
+                prop_assert!(paths.contains(kept));
+            }
+        }
+
+        #[test]
+        fn removed_paths_do_not_overlap(paths in prop::collection::vec(path_strategy(), 0..40)) {
+            let remove = updated(&paths);
+
+            for (i, first) in remove.iter().enumerate() {
+                for (j, second) in remove.iter().enumerate() {
+                    prop_assert!(i == j || !(is_folder(second) && Path::new(first).starts_with(second)));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn filter_combined_rules() {
+    use super::data::{Data};
+    use super::super::filter::Filter;
+
+    let mut some_data = Data::new("");
+    some_data.set_removes(vec![
+        "/app_core/cartridge/templates/default/home.isml".to_owned(),
+        "/app_core/cartridge/scripts/home.js".to_owned(),
+        "/app_core/cartridge/static/default/css/home.css".to_owned(),
+        "/app_core_ext/cartridge/scripts/home.js".to_owned(),
+        "/app_core/node_modules/lib/index.js".to_owned(),
+    ]);
+
+    let filter = Filter::under(&vec!["app_core".to_owned()])
+        .and(Filter::exclude(&vec!["node_modules".to_owned()]))
+        .and(Filter::extensions(&["isml", ".js"]));
+
+    some_data.filter(&filter);
+
+    assert_eq!(some_data.removes(), vec![
+        "/app_core/cartridge/templates/default/home.isml".to_owned(),
+        "/app_core/cartridge/scripts/home.js".to_owned(),
+    ]);
+}
+
+#[test]
+fn filter_paths_with_and_without_leading_slash() {
+    use super::data::{Data};
+    use super::super::filter::{Filter, Entry};
+
+    let regex = Filter::regex(r"^app_core/cartridge/").unwrap();
+    assert!(regex.matches_path("/app_core/cartridge/scripts/home.js"));
+    assert!(regex.matches(&Entry::new("app_core/cartridge/scripts/home.js", None)));
+
+    // folders are kept by extension rules so removing them still reaches the Sandbox
+    let mut some_data = Data::new("");
+    some_data.set_removes(vec![
+        "/app_core/cartridge/static/default/dist".to_owned(),
+        "/app_core/cartridge/scripts/home.js".to_owned(),
+        "/app_core/cartridge/static/default/css/home.css".to_owned(),
+    ]);
+    some_data.filter(&Filter::extensions(&["js"]));
+
+    assert_eq!(some_data.removes(), vec![
+        "/app_core/cartridge/static/default/dist".to_owned(),
+        "/app_core/cartridge/scripts/home.js".to_owned(),
+    ]);
+}
+
+#[test]
+fn change_set_round_trip() {
+    use std::path::PathBuf;
+    use super::data::{Data};
+    use super::change_set::{ChangeSet};
+
+    let mut some_data = Data::new("/base");
+    some_data.push_remove(PathBuf::from("/base/app/dist"));
+    some_data.push_create(PathBuf::from("/base/app/dist/main.js"));
+
+    let mut change_set = ChangeSet::from_json(&ChangeSet::new(&some_data).to_json().unwrap()).unwrap();
+    change_set.rebase("/other");
+
+    assert_eq!(change_set.describe(), vec!["[1] [D] /app/dist".to_owned(), "[2] [U] /app/dist/main.js".to_owned()]);
+    match &change_set.stages[1][0] {
+        super::data::Operation::Upload(file) => assert_eq!(file.full_path, "/other/app/dist/main.js"),
+        _ => panic!("expected an upload"),
+    }
+}
+
+#[test]
+fn collection_synthetic_events() {
+    use std::path::PathBuf;
+    use super::collection::{Collection};
+    use super::super::event::FileEvent;
+    use super::super::filter::Filter;
+
+    let mut collection = Collection::init("/base", vec!["app_core".to_owned()], vec!["node_modules".to_owned()]);
+    collection.parse_event(FileEvent::Write(PathBuf::from("/base/app_core/cartridge/scripts/home.js")));
+    collection.parse_event(FileEvent::Write(PathBuf::from("/base/app_core/node_modules/lib/index.js")));
+    collection.parse_event(FileEvent::Write(PathBuf::from("/base/app_other/cartridge/scripts/home.js")));
+    collection.parse_event(FileEvent::Chmod(PathBuf::from("/base/app_core/cartridge/scripts/chmod.js")));
+
+    let upload: Vec<String> = collection.get_data(&Filter::everything()).uploads().iter().map(|file| file.rel_path.clone()).collect();
+
+    assert_eq!(upload, vec!["/app_core/cartridge/scripts/home.js".to_owned()]);
+}
+
+#[test]
+fn collection_removed_cartridge() {
+    use std::path::PathBuf;
+    use super::collection::{Collection};
+    use super::super::event::FileEvent;
+    use super::super::filter::Filter;
+
+    let mut collection = Collection::init("/base", vec!["app_core".to_owned(), "app_old".to_owned()], vec![]);
+    collection.parse_event(FileEvent::Write(PathBuf::from("/base/app_old/cartridge/home.js")));
+    collection.remove_cartridge("app_old");
+    collection.parse_event(FileEvent::Remove(PathBuf::from("/base/app_old")));
+
+    assert_eq!(collection.get_cartridges(), &vec!["app_core".to_owned()]);
+    assert_eq!(collection.get_data(&Filter::everything()).removes(), vec!["/app_old".to_owned()]);
+
+    // later events of the removed cartridge are not collected anymore
+    collection.parse_event(FileEvent::Write(PathBuf::from("/base/app_old/cartridge/home.js")));
+    assert!(collection.get_data(&Filter::everything()).uploads().is_empty());
+}
+
+#[test]
+fn collection_atomic_saves() {
+    use std::path::PathBuf;
+    use super::collection::{Collection};
+    use super::super::event::FileEvent;
+    use super::super::filter::Filter;
+    use super::super::manifest::{Manifest, Stat};
+
+    let mut synced = Manifest::new();
+    synced.files.insert("/app/home.js".to_owned(), Stat { size: 1, modified: 1 });
+    synced.files.insert("/app/main.isml".to_owned(), Stat { size: 1, modified: 1 });
+    synced.files.insert("/app/old.js".to_owned(), Stat { size: 1, modified: 1 });
+
+    let mut collection = Collection::init("/base", vec!["app".to_owned()], vec![]);
+    collection.set_synced(std::sync::Arc::new(std::sync::Mutex::new(synced)));
+
+    // vim
+    collection.parse_event(FileEvent::Create(PathBuf::from("/base/app/4913")));
+    collection.parse_event(FileEvent::Remove(PathBuf::from("/base/app/4913")));
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/home.js"), PathBuf::from("/base/app/home.js~")));
+    collection.parse_event(FileEvent::Create(PathBuf::from("/base/app/home.js")));
+    // IntelliJ
+    collection.parse_event(FileEvent::Create(PathBuf::from("/base/app/main.isml.___jb_tmp___")));
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/main.isml"), PathBuf::from("/base/app/main.isml.___jb_old___")));
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/main.isml.___jb_tmp___"), PathBuf::from("/base/app/main.isml")));
+    collection.parse_event(FileEvent::Remove(PathBuf::from("/base/app/main.isml.___jb_old___")));
+    // temp file that was never synced renamed over an existing file
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/.tmp-123.js"), PathBuf::from("/base/app/old.js")));
+
+    let data = collection.get_data(&Filter::everything());
+    let upload: Vec<String> = data.uploads().iter().map(|file| file.rel_path.clone()).collect();
+
+    assert_eq!(upload, vec!["/app/home.js".to_owned(), "/app/main.isml".to_owned(), "/app/old.js".to_owned()]);
+    assert_eq!(data.removes().len(), 0);
+    assert!(data.renames().is_empty());
+}
+
+#[test]
+fn collection_renames_without_synced_files() {
+    use std::path::PathBuf;
+    use super::collection::{Collection};
+    use super::super::event::FileEvent;
+    use super::super::filter::Filter;
+
+    let mut collection = Collection::init("/base", vec!["app".to_owned()], vec![]);
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/js"), PathBuf::from("/base/app/scripts")));
+    collection.parse_event(FileEvent::Rename(PathBuf::from("/base/app/a.js"), PathBuf::from("/base/app/b.js")));
+
+    let data = collection.get_data(&Filter::everything());
+    let renames: Vec<(String, String)> = data.renames().iter().map(|rename| (rename.current.clone(), rename.new.clone())).collect();
+
+    assert_eq!(renames, vec![
+        ("/app/js".to_owned(), "/app/scripts".to_owned()),
+        ("/app/a.js".to_owned(), "/app/b.js".to_owned()),
+    ]);
+    assert!(data.removes().is_empty());
+    assert!(data.uploads().is_empty());
+}
+
+#[test]
+fn unchanged_uploads_are_dropped() {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::data::{Data};
+    use super::change_set::{ChangeSet};
+    use super::super::manifest::{Manifest, Uploaded, read_for_upload};
+
+    let base = std::env::temp_dir().join(format!("rustyuploader-hash-{}", std::process::id()));
+    fs::create_dir_all(base.join("app")).unwrap();
+    fs::write(base.join("app/same.js"), "same").unwrap();
+    fs::write(base.join("app/changed.js"), "old").unwrap();
+    let base_path = base.to_str().unwrap();
+
+    let mut first = Data::new(base_path);
+    first.push_write(base.join("app/same.js"));
+    first.push_write(base.join("app/changed.js"));
+    let mut uploaded = Uploaded::new();
+    for file in first.uploads().iter() {
+        let (_, stat, hash) = read_for_upload(&PathBuf::from(&file.full_path)).unwrap();
+        uploaded.insert(file.rel_path.clone(), (stat, hash));
+    }
+    let mut synced = Manifest::new();
+    synced.record(&ChangeSet::new(&first), &uploaded);
+
+    fs::write(base.join("app/same.js"), "same").unwrap();
+    fs::write(base.join("app/changed.js"), "new").unwrap();
+    fs::write(base.join("app/new.js"), "new").unwrap();
+    let mut second = Data::new(base_path);
+    for name in ["same.js", "changed.js", "new.js"].iter() {
+        second.push_write(PathBuf::from(format!("{}/app/{}", base_path, name)));
+    }
+
+    assert_eq!(synced.drop_unchanged(&mut second), 1);
+    let upload: Vec<String> = second.uploads().iter().map(|file| file.rel_path.clone()).collect();
+    assert_eq!(upload, vec!["/app/changed.js".to_owned(), "/app/new.js".to_owned()]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn collection_checkout_seen_by_watcher_and_git() {
+    use std::fs;
+    use super::collection::{Collection};
+    use super::super::event::FileEvent;
+    use super::super::filter::Filter;
+
+    let base = std::env::temp_dir().join(format!("rustyuploader-checkout-{}", std::process::id()));
+    fs::create_dir_all(base.join("app")).unwrap();
+    fs::write(base.join("app/b.js"), "moved").unwrap();
+
+    // the watcher sees a remove and a create, git reports the rename
+    let mut collection = Collection::init(base.to_str().unwrap(), vec!["app".to_owned()], vec![]);
+    collection.parse_event(FileEvent::Remove(base.join("app/a.js")));
+    collection.parse_event(FileEvent::Create(base.join("app/b.js")));
+    collection.parse_event(FileEvent::Rename(base.join("app/a.js"), base.join("app/b.js")));
+
+    let data = collection.get_data(&Filter::everything());
+    let renames: Vec<(String, String)> = data.renames().iter().map(|rename| (rename.current.clone(), rename.new.clone())).collect();
+
+    assert_eq!(renames, vec![("/app/a.js".to_owned(), "/app/b.js".to_owned())]);
+    assert!(data.removes().is_empty());
+    assert!(data.uploads().is_empty());
+
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+/// Set of folders stored as a tree of path components
+/// Checking if a path is inside any of the folders costs only the depth of the path
+#[derive(Debug, Clone, Default)]
+pub struct PathTree {
+    root: Node,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: HashMap<String, Node>,
+    is_end: bool,
+}
+
+impl PathTree {
+    pub fn new() -> Self {
+        PathTree { root: Node::default() }
+    }
+
+    pub fn insert(&mut self, path: &str) -> () {
+        let mut node = &mut self.root;
+
+        for component in components(path) {
+            node = node.children.entry(component.to_owned()).or_insert_with(Node::default);
+        }
+
+        node.is_end = true;
+    }
+
+    /// Checks if the path or any of its ancestors was inserted
+    pub fn covers(&self, path: &str) -> bool {
+        let mut node = &self.root;
+
+        if node.is_end {
+            return true;
+        }
+
+        for component in components(path) {
+            match node.children.get(component) {
+                Some(child) if child.is_end => return true,
+                Some(child) => node = child,
+                None => return false,
+            }
+        }
+
+        false
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split("/").filter(|component| !component.is_empty())
+}
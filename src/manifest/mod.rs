@@ -0,0 +1,318 @@
+use serde::{Serialize, Deserialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::filter::{Filter, Entry};
+use super::lot;
+use super::symlinks::{Symlinks, is_loop};
+
+/// Size and modification time (milliseconds since the unix epoch) of a synced file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stat {
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl Stat {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0);
+
+        Some(Stat { size: metadata.len(), modified: modified })
+    }
+}
+
+/// Stat and content hash of the uploaded files as they were read for the upload, keyed by their relative path
+pub type Uploaded = BTreeMap<String, (Stat, u64)>;
+
+/// Files known to be on the Sandbox, keyed by their path relative to the cartridges path
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub files: BTreeMap<String, Stat>,
+    /// Content hashes of the files uploaded since the manifest was created
+    #[serde(default)]
+    pub hashes: BTreeMap<String, u64>,
+    /// Paths uploaded or renamed by the batch being pushed, counted as synced until the push ends
+    #[serde(skip)]
+    pending: BTreeSet<String>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Manifest { files: BTreeMap::new(), hashes: BTreeMap::new(), pending: BTreeSet::new() }
+    }
+
+    /// Lists the local files of the cartridges
+    /// Folders matching the ignore list are skipped, files have to match the filter
+    /// Symbolic links are listed under their own path when the policy accepts them, otherwise skipped with a warning
+    pub fn scan(base_path: &str, cartridges: &Vec<String>, ignore_list: &Vec<String>, filter: &Filter, symlinks: Symlinks) -> Self {
+        let mut manifest = Manifest::new();
+        let ignore = Filter::include(ignore_list);
+
+        for cartridge in cartridges.iter() {
+            let cartridge_path = PathBuf::from(format!("{}/{}", base_path, cartridge));
+            let walker = symlinks.walk(&cartridge_path).into_iter()
+                .filter_entry(|entry| relative_path(base_path, entry.path()).map(|path| !ignore.matches_path(&path)).unwrap_or(false));
+
+            for entry in walker {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) if is_loop(&e) => {
+                        println!("Skipping {:?}: the symbolic link loops back to a parent folder", e.path());
+                        continue;
+                    },
+                    Err(_) => continue,
+                };
+
+                if !symlinks.accepts(&entry) {
+                    println!("Skipping symbolic link {:?}", entry.path());
+                    continue;
+                }
+
+                if !entry.path().is_file() {
+                    continue;
+                }
+
+                let rel_path = match relative_path(base_path, entry.path()) {
+                    Some(path) => path,
+                    None => continue,
+                };
+
+                if !filter.matches(&Entry::new(&rel_path, Some(entry.path()))) {
+                    continue;
+                }
+
+                if let Some(stat) = Stat::of(entry.path()) {
+                    manifest.files.insert(rel_path, stat);
+                }
+            }
+        }
+
+        manifest
+    }
+
+    /// Keeps only the files that a scan with the same arguments would list
+    pub fn restrict(&mut self, cartridges: &Vec<String>, ignore_list: &Vec<String>, filter: &Filter) -> () {
+        let cartridges = Filter::under(cartridges);
+        let ignore = Filter::include(ignore_list);
+
+        let keep = |path: &String| cartridges.matches_path(path) && !ignore.matches_path(path) && filter.matches_path(path);
+
+        self.files.retain(|path, _| keep(path));
+        self.hashes.retain(|path, _| keep(path));
+    }
+
+    /// Checks if the path is a synced file or a folder containing synced files
+    /// Paths of the batch being pushed are included, see set_pending
+    pub fn contains(&self, path: &str) -> bool {
+        let folder = format!("{}/", path.trim_end_matches("/"));
+
+        self.files.contains_key(path) || self.pending.contains(path)
+            || self.files.range(folder.clone()..).next().map(|(file, _)| lot::is_inside(file, path)).unwrap_or(false)
+            || self.pending.range(folder..).next().map(|file| lot::is_inside(file, path)).unwrap_or(false)
+    }
+
+    /// Marks the paths the change set uploads or renames to while it is pushed
+    /// Events collected meanwhile see them as synced, e.g. a file renamed while its upload is in flight
+    pub fn set_pending(&mut self, change_set: &lot::ChangeSet) -> () {
+        for operation in change_set.stages.iter().flat_map(|stage| stage.iter()) {
+            match operation {
+                lot::Operation::Upload(file) => self.pending.insert(file.rel_path.clone()),
+                lot::Operation::Rename(rename) => self.pending.insert(rename.new.clone()),
+                lot::Operation::Remove(_) => false,
+            };
+        }
+    }
+
+    /// The push ended, only the recorded operations are synced
+    pub fn clear_pending(&mut self) -> () {
+        self.pending.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Changes needed to turn this manifest into the other one
+    pub fn diff(&self, other: &Manifest, base_path: &str) -> lot::Data {
+        self.diff_with(other, base_path, |stat, other_stat| stat == other_stat)
+    }
+
+    /// Same as diff but files are compared by size only
+    /// Used against remote listings where the modification time is the upload time
+    pub fn diff_sizes(&self, other: &Manifest, base_path: &str) -> lot::Data {
+        self.diff_with(other, base_path, |stat, other_stat| stat.size == other_stat.size)
+    }
+
+    fn diff_with<F: Fn(&Stat, &Stat) -> bool>(&self, other: &Manifest, base_path: &str, same: F) -> lot::Data {
+        let mut data = lot::Data::new(base_path);
+
+        for (rel_path, stat) in other.files.iter() {
+            if !self.files.get(rel_path).map(|old_stat| same(old_stat, stat)).unwrap_or(false) {
+                data.push_write(PathBuf::from(format!("{}{}", base_path, rel_path)));
+            }
+        }
+
+        for rel_path in self.files.keys().filter(|rel_path| !other.files.contains_key(*rel_path)) {
+            data.push_remove(PathBuf::from(format!("{}{}", base_path, rel_path)));
+        }
+
+        data.update();
+
+        data
+    }
+
+    /// Updates the manifest with the operations of a pushed change set
+    /// Only the operations that reached the Sandbox have to be recorded, see ChangeSet::without
+    /// Uploads are recorded with the content that was sent, a file saved again meanwhile stays changed
+    pub fn record(&mut self, change_set: &lot::ChangeSet, uploaded: &Uploaded) -> () {
+        for stage in change_set.stages.iter() {
+            for operation in stage.iter() {
+                match operation {
+                    lot::Operation::Rename(rename) => {
+                        let moved: Vec<(String, Stat)> = self.take_inside(&rename.current).into_iter()
+                            .map(|(path, stat)| (format!("{}{}", rename.new, &path[rename.current.len()..]), stat))
+                            .collect();
+                        let moved_hashes: Vec<(String, u64)> = take_inside(&mut self.hashes, &rename.current).into_iter()
+                            .map(|(path, hash)| (format!("{}{}", rename.new, &path[rename.current.len()..]), hash))
+                            .collect();
+
+                        self.files.extend(moved);
+                        self.hashes.extend(moved_hashes);
+                    },
+                    lot::Operation::Upload(file) => {
+                        match uploaded.get(&file.rel_path) {
+                            Some((stat, hash)) => {
+                                self.files.insert(file.rel_path.clone(), stat.clone());
+                                self.hashes.insert(file.rel_path.clone(), *hash);
+                            },
+                            None => {
+                                self.files.remove(&file.rel_path);
+                                self.hashes.remove(&file.rel_path);
+                            },
+                        }
+                    },
+                    lot::Operation::Remove(path) => {
+                        self.take_inside(path);
+                        take_inside(&mut self.hashes, path);
+                    },
+                }
+            }
+        }
+    }
+
+    /// Puts back the known entries of the paths the changes upload or remove
+    /// They are synced only once the changes are pushed and recorded, a failed push leaves them to the next startup
+    pub fn revert(&mut self, known: &Manifest, data: &lot::Data) -> () {
+        for file in data.uploads().iter() {
+            match known.files.get(&file.rel_path) {
+                Some(stat) => self.files.insert(file.rel_path.clone(), stat.clone()),
+                None => self.files.remove(&file.rel_path),
+            };
+        }
+
+        for path in data.removes().iter() {
+            for rel_path in paths_inside(&known.files, path).into_iter() {
+                self.files.insert(rel_path.clone(), known.files[&rel_path].clone());
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| format!("Unable to serialize manifest: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Unable to write manifest at path: {}\nError: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Unable to parse manifest: {}", e)),
+            Err(e) => Err(format!("Unable to read manifest at path: {}\nError: {}", path, e)),
+        }
+    }
+
+    /// Drops the uploads of files whose content did not change since they were uploaded
+    /// Returns the number of dropped uploads
+    pub fn drop_unchanged(&self, data: &mut lot::Data) -> usize {
+        let count = data.uploads().len();
+        data.retain_uploads(|file: &lot::File| {
+            self.hashes.get(&file.rel_path)
+                .map(|hash| content_hash(Path::new(&file.full_path)) != Some(*hash))
+                .unwrap_or(true)
+        });
+
+        count - data.uploads().len()
+    }
+
+    /// Replaces the files of the cartridges by the scanned ones, the other cartridges are kept
+    pub fn replace_cartridges(&mut self, cartridges: &[String], scanned: Manifest) {
+        for cartridge in cartridges.iter() {
+            let folder = format!("/{}", cartridge);
+            self.take_inside(&folder);
+            take_inside(&mut self.hashes, &folder);
+        }
+
+        self.files.extend(scanned.files);
+        self.hashes.extend(scanned.hashes);
+    }
+
+    fn take_inside(&mut self, folder: &str) -> Vec<(String, Stat)> {
+        take_inside(&mut self.files, folder)
+    }
+}
+
+/// Removes the folder and every path inside it
+fn take_inside<T>(map: &mut BTreeMap<String, T>, folder: &str) -> Vec<(String, T)> {
+    paths_inside(map, folder).into_iter()
+        .filter_map(|path| map.remove(&path).map(|value| (path, value)))
+        .collect()
+}
+
+/// The folder itself and every path inside it, found with a range of the sorted keys
+fn paths_inside<T>(map: &BTreeMap<String, T>, folder: &str) -> Vec<String> {
+    let folder = folder.trim_end_matches("/");
+    let mut paths: Vec<String> = map.range(format!("{}/", folder)..)
+        .map(|(path, _)| path)
+        .take_while(|path| lot::is_inside(path, folder))
+        .cloned()
+        .collect();
+    if map.contains_key(folder) {
+        paths.push(folder.to_owned());
+    }
+
+    paths
+}
+
+/// FNV-1a hash of the file content, stable between runs so it can be saved with the manifest
+pub fn content_hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|bytes| hash_bytes(&bytes))
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash: u64, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Reads a file to upload it, together with its stat and the hash of the bytes read
+/// The stat is taken first so a file saved meanwhile still looks modified on the next comparison
+pub fn read_for_upload(path: &Path) -> Result<(Vec<u8>, Stat, u64), String> {
+    let stat = Stat::of(path).ok_or(format!("Unable to open file at path: {:?}", path))?;
+    let bytes = fs::read(path).map_err(|e| format!("Unable to open file at path: {:?}\nError: {}", path, e))?;
+    let hash = hash_bytes(&bytes);
+
+    Ok((bytes, stat, hash))
+}
+
+/// Path relative to the cartridges path in the same form used by lot::Data (e.g. /app_core/cartridge/file.js)
+fn relative_path(base_path: &str, path: &Path) -> Option<String> {
+    path.strip_prefix(base_path).ok()
+        .and_then(|path| path.to_str())
+        .map(|path| format!("/{}", path.replace("\\", "/")))
+}
+
+#[cfg(test)]
+mod tests;
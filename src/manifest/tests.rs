@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use super::{Manifest, Stat, Uploaded, read_for_upload};
+use super::super::lot::{Data, ChangeSet, Operation};
+use super::super::filter::Filter;
+
+/// Reads the uploads of the change set the way the Sandbox does before sending them
+fn read(change_set: &ChangeSet) -> Uploaded {
+    change_set.stages.iter().flat_map(|stage| stage.iter())
+        .filter_map(|operation| match operation {
+            Operation::Upload(file) => read_for_upload(&PathBuf::from(&file.full_path)).ok()
+                .map(|(_, stat, hash)| (file.rel_path.clone(), (stat, hash))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn manifest(paths: &[&str]) -> Manifest {
+    let mut manifest = Manifest::new();
+    for path in paths.iter() {
+        manifest.files.insert(path.to_string(), Stat { size: 1, modified: 1 });
+        manifest.hashes.insert(path.to_string(), 1);
+    }
+
+    manifest
+}
+
+#[test]
+fn record_moves_and_removes_whole_folders() {
+    let mut synced = manifest(&["/app/js/a.js", "/app/js/lib/b.js", "/app/jsx/c.js", "/app/old/d.js", "/app/old.js"]);
+
+    let mut data = Data::new("/base");
+    data.push_rename(PathBuf::from("/base/app/js"), PathBuf::from("/base/app/scripts"));
+    data.push_remove(PathBuf::from("/base/app/old"));
+    synced.record(&ChangeSet::new(&data), &Uploaded::new());
+
+    let files: Vec<&String> = synced.files.keys().collect();
+    assert_eq!(files, vec!["/app/jsx/c.js", "/app/old.js", "/app/scripts/a.js", "/app/scripts/lib/b.js"]);
+    assert_eq!(synced.hashes.keys().collect::<Vec<&String>>(), files);
+}
+
+#[test]
+fn only_pushed_operations_are_recorded() {
+    let mut synced = manifest(&["/app/a.js"]);
+
+    let mut data = Data::new("/base");
+    data.push_rename(PathBuf::from("/base/app/a.js"), PathBuf::from("/base/app/b.js"));
+    data.push_remove(PathBuf::from("/base/app/dist"));
+    let change_set = ChangeSet::new(&data);
+
+    // renamed while the batch is pushed
+    synced.set_pending(&change_set);
+    assert!(synced.contains("/app/b.js"));
+
+    let failed = vec![(change_set.stages[0][0].clone(), "Unable to rename".to_owned())];
+    synced.clear_pending();
+    synced.record(&change_set.without(&failed), &Uploaded::new());
+
+    assert!(synced.contains("/app/a.js"));
+    assert!(!synced.contains("/app/b.js"));
+}
+
+#[test]
+fn startup_diff_keeps_watched_files() {
+    let mut known = Manifest::new();
+    known.files.insert("/app/home.js".to_owned(), Stat { size: 1, modified: 1 });
+    known.files.insert("/app/node_modules/lib.js".to_owned(), Stat { size: 1, modified: 1 });
+    known.files.insert("/app/kept.js".to_owned(), Stat { size: 2, modified: 1 });
+    known.files.insert("/other/home.js".to_owned(), Stat { size: 1, modified: 1 });
+    known.restrict(&vec!["app".to_owned()], &vec!["node_modules".to_owned()], &Filter::everything());
+
+    let mut local = Manifest::new();
+    local.files.insert("/app/kept.js".to_owned(), Stat { size: 2, modified: 5 });
+
+    assert_eq!(known.diff_sizes(&local, "/base").removes(), vec!["/app/home.js".to_owned()]);
+}
+
+#[test]
+fn startup_changes_are_synced_once_pushed() {
+    let known = manifest(&["/app/changed.js", "/app/old/a.js", "/app/old/b.js", "/app/same.js"]);
+
+    let mut local = Manifest::new();
+    local.files.insert("/app/changed.js".to_owned(), Stat { size: 2, modified: 2 });
+    local.files.insert("/app/new.js".to_owned(), Stat { size: 2, modified: 2 });
+    local.files.insert("/app/same.js".to_owned(), Stat { size: 1, modified: 1 });
+
+    let mut data = Data::new("/base");
+    data.push_write(PathBuf::from("/base/app/changed.js"));
+    data.push_write(PathBuf::from("/base/app/new.js"));
+    data.push_remove(PathBuf::from("/base/app/old"));
+    local.revert(&known, &data);
+
+    assert_eq!(local.files.keys().collect::<Vec<&String>>(), vec!["/app/changed.js", "/app/old/a.js", "/app/old/b.js", "/app/same.js"]);
+    assert_eq!(local.files["/app/changed.js"], Stat { size: 1, modified: 1 });
+}
+
+#[test]
+fn failed_uploads_keep_no_hash() {
+    let base = std::env::temp_dir().join(format!("rustyuploader-failed-hash-{}", std::process::id()));
+    std::fs::create_dir_all(base.join("app")).unwrap();
+    std::fs::write(base.join("app/pushed.js"), "pushed").unwrap();
+    std::fs::write(base.join("app/failed.js"), "failed").unwrap();
+
+    let mut data = Data::new(base.to_str().unwrap());
+    data.push_write(base.join("app/pushed.js"));
+    data.push_write(base.join("app/failed.js"));
+    let change_set = ChangeSet::new(&data);
+    let failed: Vec<_> = change_set.stages[0].iter()
+        .filter(|operation| match operation {
+            Operation::Upload(file) => file.rel_path == "/app/failed.js",
+            _ => false,
+        })
+        .map(|operation| (operation.clone(), "Unable to upload".to_owned()))
+        .collect();
+
+    let mut synced = Manifest::new();
+    synced.record(&change_set.without(&failed), &read(&change_set));
+
+    assert_eq!(synced.hashes.keys().collect::<Vec<&String>>(), vec!["/app/pushed.js"]);
+    assert_eq!(synced.hashes["/app/pushed.js"], super::content_hash(&base.join("app/pushed.js")).unwrap());
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn uploads_are_recorded_as_they_were_sent() {
+    let base = std::env::temp_dir().join(format!("rustyuploader-sent-hash-{}", std::process::id()));
+    std::fs::create_dir_all(base.join("app")).unwrap();
+    std::fs::write(base.join("app/a.js"), "first").unwrap();
+
+    let mut data = Data::new(base.to_str().unwrap());
+    data.push_write(base.join("app/a.js"));
+    let change_set = ChangeSet::new(&data);
+    let uploaded = read(&change_set);
+
+    // saved again while the batch is in flight
+    std::fs::write(base.join("app/a.js"), "second").unwrap();
+    let mut synced = Manifest::new();
+    synced.record(&change_set, &uploaded);
+
+    assert_eq!(synced.hashes["/app/a.js"], super::hash_bytes(b"first"));
+    assert_eq!(synced.files["/app/a.js"].size, 5);
+
+    let mut next = Data::new(base.to_str().unwrap());
+    next.push_write(base.join("app/a.js"));
+    assert_eq!(synced.drop_unchanged(&mut next), 0);
+    assert_eq!(next.len(), 1);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn unreadable_files_are_not_uploaded() {
+    let missing = std::env::temp_dir().join(format!("rustyuploader-missing-{}.js", std::process::id()));
+
+    assert!(read_for_upload(&missing).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn scan_lists_links_by_policy() {
+    use std::os::unix::fs::symlink;
+    use super::super::symlinks::Symlinks;
+
+    let base = std::env::temp_dir().join(format!("rustyuploader-scan-links-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(base.join("app")).unwrap();
+    std::fs::create_dir_all(base.join("shared/assets")).unwrap();
+    std::fs::write(base.join("app/home.js"), "home").unwrap();
+    std::fs::write(base.join("shared/assets/logo.svg"), "<svg>").unwrap();
+    std::fs::write(base.join("shared/readme.md"), "shared").unwrap();
+    symlink(base.join("shared/assets"), base.join("app/assets")).unwrap();
+    symlink(base.join("shared/readme.md"), base.join("app/readme.md")).unwrap();
+    symlink(base.join("app"), base.join("app/self")).unwrap();
+
+    let files = |symlinks: Symlinks| -> Vec<String> {
+        Manifest::scan(base.to_str().unwrap(), &vec!["app".to_owned()], &vec![], &Filter::everything(), symlinks)
+            .files.keys().cloned().collect()
+    };
+
+    assert_eq!(files(Symlinks::Follow), vec!["/app/assets/logo.svg", "/app/home.js", "/app/readme.md"]);
+    assert_eq!(files(Symlinks::Content), vec!["/app/home.js", "/app/readme.md"]);
+    assert_eq!(files(Symlinks::Skip), vec!["/app/home.js"]);
+    assert_eq!(Manifest::scan(base.to_str().unwrap(), &vec!["app".to_owned()], &vec![], &Filter::everything(), Symlinks::Follow)
+        .files["/app/readme.md"].size, 6);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn replaced_cartridges_keep_the_other_ones() {
+    let mut synced = manifest(&["/app_core/old.js", "/app_core_ext/a.js", "/app_storefront/b.js"]);
+    let scanned = manifest(&["/app_core/new.js"]);
+
+    synced.replace_cartridges(&vec!["app_core".to_owned()], scanned);
+
+    let files: Vec<&String> = synced.files.keys().collect();
+    assert_eq!(files, vec!["/app_core/new.js", "/app_core_ext/a.js", "/app_storefront/b.js"]);
+    assert_eq!(synced.hashes.keys().collect::<Vec<&String>>(), files);
+}
@@ -0,0 +1,277 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::event::FileEvent;
+use super::manifest::content_hash;
+use super::watch::Target;
+use super::symlinks::Symlinks;
+
+/// How a renamed file is recognized between two scans
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Identity {
+    /// Same inode, only available on unix
+    Inode,
+    /// Same content
+    Hash,
+}
+
+impl Identity {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "hash" => Identity::Hash,
+            _ if cfg!(unix) => Identity::Inode,
+            _ => Identity::Hash,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Stat {
+    modified: Option<SystemTime>,
+    size: u64,
+    is_dir: bool,
+    id: Option<u64>,
+}
+
+type Snapshot = HashMap<PathBuf, Stat>;
+
+/// Watcher scanning the targets at a fixed interval
+/// Used where file system notifications are not delivered (NFS, SMB, container volumes)
+pub struct Poller {
+    targets: Arc<Mutex<Vec<Target>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Poller {
+    /// Links are scanned through according to the policy, so their targets are compared as well
+    pub fn start(tx: Sender<FileEvent>, interval: Duration, identity: Identity, symlinks: Symlinks) -> Self {
+        let targets: Arc<Mutex<Vec<Target>>> = Arc::new(Mutex::new(vec![]));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_targets = Arc::clone(&targets);
+        let thread_running = Arc::clone(&running);
+
+        thread::spawn(move || {
+            let mut snapshots: HashMap<PathBuf, Snapshot> = HashMap::new();
+            let mut skipped: HashSet<PathBuf> = HashSet::new();
+
+            while thread_running.load(Ordering::SeqCst) {
+                let current_targets: Vec<Target> = thread_targets.lock().unwrap().clone();
+                snapshots.retain(|path, _| current_targets.iter().any(|target| &target.path == path));
+
+                for target in current_targets.iter() {
+                    let previous = snapshots.get(&target.path);
+                    let snapshot = scan(target, identity, symlinks, previous, &mut skipped);
+
+                    // new targets are only recorded, the same way a new notify watch reports nothing
+                    if let Some(previous) = previous {
+                        for event in diff(previous, &snapshot).into_iter() {
+                            if tx.send(event).is_err() {
+                                return;
+                            }
+                        }
+                    }
+
+                    snapshots.insert(target.path.clone(), snapshot);
+                }
+
+                thread::sleep(interval);
+            }
+        });
+
+        Poller { targets: targets, running: running }
+    }
+
+    /// Watching an already watched path replaces its recursive mode
+    pub fn watch(&self, target: Target) -> Result<(), String> {
+        if !target.path.exists() {
+            return Err(format!("Path does not exist: {:?}", target.path));
+        }
+
+        let mut targets = self.targets.lock().unwrap();
+        targets.retain(|watched| watched.path != target.path);
+        targets.push(target);
+
+        Ok(())
+    }
+
+    pub fn unwatch(&self, path: &Path) -> Result<(), String> {
+        self.targets.lock().unwrap().retain(|target| target.path != path);
+        Ok(())
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Takes a snapshot of everything inside the target, the target itself is not included
+/// File hashes are reused from the previous snapshot when the file did not change
+/// Links left out by the policy are skipped with a warning, once per link
+fn scan(target: &Target, identity: Identity, symlinks: Symlinks, previous: Option<&Snapshot>, skipped: &mut HashSet<PathBuf>) -> Snapshot {
+    let max_depth = if target.recursive { usize::max_value() } else { 1 };
+    let mut snapshot = Snapshot::new();
+
+    for entry in symlinks.walk(&target.path).min_depth(1).max_depth(max_depth).into_iter().filter_map(|e| e.ok()) {
+        if !symlinks.accepts(&entry) {
+            if skipped.insert(entry.path().to_path_buf()) {
+                println!("Skipping symbolic link {:?}", entry.path());
+            }
+            continue;
+        }
+
+        // the target of an accepted link is compared, not the link itself
+        let metadata = match fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        let mut stat = Stat {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            is_dir: metadata.is_dir(),
+            id: None,
+        };
+
+        stat.id = match identity {
+            Identity::Inode => inode(&metadata),
+            Identity::Hash if stat.is_dir => None,
+            Identity::Hash => {
+                match previous.and_then(|previous| previous.get(entry.path())) {
+                    Some(old) if old.modified == stat.modified && old.size == stat.size => old.id,
+                    _ => content_hash(entry.path()),
+                }
+            },
+        };
+
+        snapshot.insert(entry.path().to_path_buf(), stat);
+    }
+
+    snapshot
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Compares two snapshots and returns the events in the order renames, removes, creates, writes
+/// Identities shared by several removed or added entries (e.g. empty files compared by hash) are not paired
+fn diff(previous: &Snapshot, current: &Snapshot) -> Vec<FileEvent> {
+    let mut removed: Vec<&PathBuf> = previous.keys().filter(|path| !current.contains_key(*path)).collect();
+    let mut added: Vec<&PathBuf> = current.keys().filter(|path| !previous.contains_key(*path)).collect();
+    removed.sort_by(|a, b| a.components().count().cmp(&b.components().count()).then(a.cmp(b)));
+    added.sort();
+
+    let removed_ids: HashMap<(u64, bool), usize> = identities(&removed, previous);
+    let mut added_ids: HashMap<(u64, bool), Option<&PathBuf>> = HashMap::new();
+    for path in added.iter() {
+        if let Some(id) = current[*path].id {
+            added_ids.entry((id, current[*path].is_dir))
+                .and_modify(|paths| *paths = None)
+                .or_insert(Some(*path));
+        }
+    }
+
+    let mut renames: Vec<(PathBuf, PathBuf)> = vec![];
+    let mut renamed_folders: HashMap<&Path, PathBuf> = HashMap::new();
+    // new path of every renamed entry to its old path
+    let mut renamed: HashMap<PathBuf, &PathBuf> = HashMap::new();
+    let mut renamed_from: HashSet<&PathBuf> = HashSet::new();
+
+    for old_path in removed.iter() {
+        // moved together with a renamed parent folder
+        let moved = old_path.ancestors().skip(1)
+            .find_map(|folder| renamed_folders.get(folder).map(|to| to.join(old_path.strip_prefix(folder).unwrap())));
+
+        if let Some(new_path) = moved {
+            if current.contains_key(&new_path) {
+                renamed_from.insert(old_path);
+                renamed.insert(new_path, old_path);
+                continue;
+            }
+        }
+
+        let old_stat = &previous[*old_path];
+        let key = match old_stat.id {
+            Some(id) => (id, old_stat.is_dir),
+            None => continue,
+        };
+
+        if removed_ids.get(&key) != Some(&1) {
+            continue;
+        }
+
+        if let Some(Some(new_path)) = added_ids.get(&key) {
+            if renamed.contains_key(*new_path) {
+                continue;
+            }
+
+            if old_stat.is_dir {
+                renamed_folders.insert(old_path.as_path(), new_path.to_path_buf());
+            }
+            renames.push((old_path.to_path_buf(), new_path.to_path_buf()));
+            renamed_from.insert(old_path);
+            renamed.insert(new_path.to_path_buf(), old_path);
+        }
+    }
+
+    let mut events: Vec<FileEvent> = renames.into_iter()
+        .map(|(from, to)| FileEvent::Rename(from, to))
+        .collect();
+
+    events.extend(removed.iter()
+        .filter(|path| !renamed_from.contains(*path))
+        .map(|path| FileEvent::Remove(path.to_path_buf())));
+
+    events.extend(added.iter()
+        .filter(|path| !renamed.contains_key(**path))
+        .map(|path| FileEvent::Create(path.to_path_buf())));
+
+    // renamed files keep their previous stat under the old path
+    let mut written: Vec<&PathBuf> = current.iter()
+        .filter(|(path, stat)| {
+            let old_path: &PathBuf = renamed.get(*path).map(|old_path| *old_path).unwrap_or(path);
+
+            !stat.is_dir && previous.get(old_path)
+                .map(|old| old.modified != stat.modified || old.size != stat.size)
+                .unwrap_or(false)
+        })
+        .map(|(path, _)| path)
+        .collect();
+    written.sort();
+
+    events.extend(written.into_iter().map(|path| FileEvent::Write(path.to_path_buf())));
+
+    events
+}
+
+/// Number of entries sharing each identity
+fn identities(paths: &Vec<&PathBuf>, snapshot: &Snapshot) -> HashMap<(u64, bool), usize> {
+    let mut counts: HashMap<(u64, bool), usize> = HashMap::new();
+
+    for path in paths.iter() {
+        if let Some(id) = snapshot[*path].id {
+            *counts.entry((id, snapshot[*path].is_dir)).or_insert(0) += 1;
+        }
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use super::{diff, Snapshot, Stat};
+use super::super::event::FileEvent;
+
+fn stat(size: u64, id: u64) -> Stat {
+    Stat { modified: None, size: size, is_dir: false, id: Some(id) }
+}
+
+fn snapshot(entries: Vec<(&str, Stat)>) -> Snapshot {
+    entries.into_iter().map(|(path, stat)| (PathBuf::from(path), stat)).collect()
+}
+
+#[test]
+fn created_and_removed_files() {
+    let previous = snapshot(vec![("/app/old.js", stat(1, 1))]);
+    let current = snapshot(vec![("/app/new.js", stat(2, 2))]);
+
+    assert_eq!(diff(&previous, &current), vec![
+        FileEvent::Remove(PathBuf::from("/app/old.js")),
+        FileEvent::Create(PathBuf::from("/app/new.js")),
+    ]);
+}
+
+#[test]
+fn renamed_folder_moves_its_files() {
+    let folder = Stat { modified: None, size: 0, is_dir: true, id: Some(10) };
+    let previous = snapshot(vec![("/app/js", folder.clone()), ("/app/js/a.js", stat(1, 1)), ("/app/js/b.js", stat(1, 2))]);
+    let mut written = stat(5, 2);
+    written.modified = Some(std::time::UNIX_EPOCH);
+    let current = snapshot(vec![("/app/scripts", folder), ("/app/scripts/a.js", stat(1, 1)), ("/app/scripts/b.js", written)]);
+
+    assert_eq!(diff(&previous, &current), vec![
+        FileEvent::Rename(PathBuf::from("/app/js"), PathBuf::from("/app/scripts")),
+        FileEvent::Write(PathBuf::from("/app/scripts/b.js")),
+    ]);
+}
+
+#[test]
+fn shared_identities_are_not_paired() {
+    // empty files compared by content all have the same hash
+    let previous = snapshot(vec![("/app/a.js", stat(0, 7)), ("/app/b.js", stat(0, 7))]);
+    let current = snapshot(vec![("/app/c.js", stat(0, 7))]);
+
+    assert_eq!(diff(&previous, &current), vec![
+        FileEvent::Remove(PathBuf::from("/app/a.js")),
+        FileEvent::Remove(PathBuf::from("/app/b.js")),
+        FileEvent::Create(PathBuf::from("/app/c.js")),
+    ]);
+
+    let previous = snapshot(vec![("/app/a.js", stat(0, 7))]);
+    let current = snapshot(vec![("/app/b.js", stat(0, 7)), ("/app/c.js", stat(0, 7))]);
+
+    assert_eq!(diff(&previous, &current).len(), 3);
+}
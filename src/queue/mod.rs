@@ -0,0 +1,134 @@
+use std::fs;
+use std::path::PathBuf;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use super::lot;
+use super::filter::Filter;
+
+/// Operations that could not be pushed while the Sandbox was unreachable
+/// Saved to disk after every change so they are replayed on the next start as well
+/// Operations on the same path are coalesced the same way as collected events
+pub struct Queue {
+    path: String,
+    data: lot::Data,
+}
+
+impl Queue {
+    /// Loads the queue saved at the path, a missing file is an empty queue
+    pub fn load(path: &str, base_path: &str) -> Self {
+        let data = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!("Unable to parse the offline queue at path: {}\nError: {}", path, e);
+                lot::Data::new(base_path)
+            }),
+            Err(_) => lot::Data::new(base_path),
+        };
+
+        Queue { path: path.to_owned(), data: data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn base_path(&self) -> &str {
+        self.data.get_base_path()
+    }
+
+    /// Adds the operations after the ones already queued
+    pub fn push(&mut self, operations: Vec<lot::Operation>) -> () {
+        let base_path = self.data.get_base_path().to_owned();
+
+        for operation in operations.into_iter() {
+            match operation {
+                lot::Operation::Rename(rename) => self.data.push_rename(
+                    PathBuf::from(format!("{}{}", base_path, rename.current)),
+                    PathBuf::from(format!("{}{}", base_path, rename.new)),
+                ),
+                lot::Operation::Upload(file) => self.data.push_write(PathBuf::from(file.full_path)),
+                lot::Operation::Remove(path) => self.data.push_remove(PathBuf::from(format!("{}{}", base_path, path))),
+            }
+        }
+
+        self.save();
+    }
+
+    /// Adds every operation of the change set, used to keep later changes behind the queued ones
+    pub fn push_change_set(&mut self, change_set: &lot::ChangeSet) -> () {
+        self.push(change_set.stages.iter().flat_map(|stage| stage.iter().cloned()).collect());
+    }
+
+    /// Empties the queue and returns its operations in push order
+    pub fn take(&mut self) -> lot::ChangeSet {
+        let change_set = lot::ChangeSet::new(&self.data);
+        self.data.reset();
+        self.save();
+
+        change_set
+    }
+
+    /// Drops the operations inside the folders, e.g. cartridges that were replaced as a whole
+    pub fn drop_under(&mut self, folders: &Vec<String>) {
+        self.data.filter(&Filter::under(folders).not());
+        self.save();
+    }
+
+    /// Human readable list of the queued operations
+    pub fn describe(&self) -> Vec<String> {
+        lot::ChangeSet::new(&self.data).describe()
+    }
+
+    fn save(&self) -> () {
+        let result = if self.is_empty() {
+            fs::remove_file(&self.path).or_else(|e| if e.kind() == std::io::ErrorKind::NotFound { Ok(()) } else { Err(e) })
+        } else {
+            serde_json::to_string_pretty(&self.data)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+                .and_then(|json| fs::write(&self.path, json))
+        };
+
+        if let Err(e) = result {
+            println!("Unable to save the offline queue at path: {}\nError: {}", self.path, e);
+        }
+    }
+}
+
+/// Longest delay between two probes of an unreachable Sandbox
+const MAX_PROBE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// When to probe the Sandbox again while operations are queued
+/// The delay doubles after every failed probe and is reset once the Sandbox is reachable
+pub struct Backoff {
+    interval: Duration,
+    delay: Duration,
+    next: Instant,
+}
+
+impl Backoff {
+    /// The first probe is due right away
+    pub fn new(interval: Duration) -> Self {
+        Backoff { interval: interval, delay: interval, next: Instant::now() }
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    pub fn probed(&mut self, reachable: bool) -> () {
+        if reachable {
+            self.delay = self.interval;
+            self.next = Instant::now();
+        } else {
+            self.next = Instant::now() + self.delay;
+            self.delay = cmp::max(self.interval, cmp::min(self.delay * 2, MAX_PROBE_INTERVAL));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{Queue, Backoff};
+use super::super::lot::{Operation, File, Data, ChangeSet};
+
+fn queue_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustyuploader-queue-{}-{}.json", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn upload(rel_path: &str) -> Operation {
+    Operation::Upload(File { full_path: format!("/base{}", rel_path), rel_path: rel_path.to_owned() })
+}
+
+#[test]
+fn queued_operations_are_saved_and_reloaded() {
+    let path = queue_path("reload");
+    let mut data = Data::new("/base");
+    data.push_rename(PathBuf::from("/base/app/old"), PathBuf::from("/base/app/new"));
+
+    let mut queue = Queue::load(path.to_str().unwrap(), "/base");
+    queue.push(vec![upload("/app/a.js"), Operation::Remove("/app/b.js".to_owned())]);
+    queue.push_change_set(&ChangeSet::new(&data));
+    assert!(path.exists());
+
+    let mut reloaded = Queue::load(path.to_str().unwrap(), "/base");
+    assert_eq!(reloaded.describe(), queue.describe());
+    assert_eq!(reloaded.len(), 3);
+
+    let change_set = reloaded.take();
+    assert_eq!(change_set.len(), 3);
+    assert!(reloaded.is_empty());
+    assert!(!path.exists());
+}
+
+#[test]
+fn operations_on_the_same_path_are_queued_once() {
+    let path = queue_path("dedup");
+    let mut queue = Queue::load(path.to_str().unwrap(), "/base");
+    queue.push(vec![upload("/app/a.js"), upload("/app/b.js")]);
+    queue.push(vec![upload("/app/a.js"), Operation::Remove("/app/b.js".to_owned())]);
+    queue.push(vec![Operation::Remove("/app/b.js".to_owned())]);
+
+    assert_eq!(queue.describe(), vec!["[1] [D] /app/b.js", "[1] [U] /app/a.js"]);
+    assert_eq!(Queue::load(path.to_str().unwrap(), "/base").len(), 2);
+
+    queue.take();
+}
+
+#[test]
+fn unparsable_queue_is_empty() {
+    let path = queue_path("broken");
+    fs::write(&path, "not json").unwrap();
+
+    assert!(Queue::load(path.to_str().unwrap(), "/base").is_empty());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failed_probes_are_spaced_out() {
+    let mut backoff = Backoff::new(Duration::from_secs(30));
+    assert!(backoff.is_due());
+
+    backoff.probed(false);
+    assert!(!backoff.is_due());
+    assert_eq!(backoff.delay, Duration::from_secs(60));
+
+    backoff.probed(false);
+    assert_eq!(backoff.delay, Duration::from_secs(120));
+
+    for _ in 0..10 {
+        backoff.probed(false);
+    }
+    assert_eq!(backoff.delay, Duration::from_secs(600));
+
+    backoff.probed(true);
+    assert!(backoff.is_due());
+    assert_eq!(backoff.delay, Duration::from_secs(30));
+}
+
+#[test]
+fn operations_inside_replaced_cartridges_are_dropped() {
+    let path = queue_path("drop-under");
+    let mut queue = Queue::load(path.to_str().unwrap(), "/base");
+    queue.push(vec![
+        upload("/app_core/cartridge/a.js"),
+        upload("/app_core_ext/cartridge/b.js"),
+        Operation::Remove("/app_core/cartridge/old.js".to_owned()),
+        Operation::Remove("/app_storefront/cartridge/old.js".to_owned()),
+    ]);
+
+    queue.drop_under(&vec!["app_core".to_owned()]);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(Queue::load(path.to_str().unwrap(), "/base").describe(), queue.describe());
+
+    queue.take();
+}
@@ -0,0 +1,362 @@
+use super::json::Config;
+use super::lot;
+use super::manifest::{Uploaded, read_for_upload};
+use super::time;
+use super::archive;
+
+use rustydav::client;
+use rustydav::prelude::*;
+
+use regex::Regex;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::io::Read;
+
+pub struct Sandbox {
+    webdav: client::Client,
+    url: String,
+    code_version: String,
+    zip_min_files: usize,
+    zip_min_bytes: u64,
+}
+
+impl Sandbox {
+    pub fn init(config: &Config) -> Self {
+        Sandbox {
+            webdav: client::Client::init(config.get_username().as_str(), config.get_password().as_str()),
+            url: format!("https://{}/on/demandware.servlet/webdav/Sites/Cartridges", config.get_hostname()),
+            code_version: config.get_code_version(),
+            zip_min_files: config.get_zip_min_files(),
+            zip_min_bytes: config.get_zip_min_bytes(),
+        }
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!("{}{}", self.url, path)
+    }
+
+    fn path_with_version(&self, path: &str) -> String {
+        format!("{}/{}{}", self.url, self.code_version, path)
+    }
+
+    fn parse_response_status(&self, result: &mut Response, message: &str) -> Result<(), String> {
+        // dbg!(&result);
+        match result.status().as_u16() {
+            200 | 201 | 204 => Ok(()),
+            401 => Err(format!("Unauthorized call! {}", message)),
+            403 => Err(format!("Forbidden, You don't have permission to {} !", message)),
+            404 => Err("Resource no longer exist".to_owned()),
+            409 => Err("Conflict, A collection cannot be made at the Request-URI until one or more intermediate collections have been created.".to_owned()),
+            502 => Err("Bad Gateway, Server refuses to accept the resource.".to_owned()),
+            507 => Err("Insufficient Storage, The destination resource does not have sufficient space to record the state of the resource after the execution of this method.".to_owned()),
+            _ => Err(format!("Request failed\nUrl: {}\nStatus: {}\nAddress: {}", result.url().as_str(), result.status(), result.remote_addr().unwrap())),
+        }
+    }
+
+    /// Gets and parses .version file from DW Sandbox to get the currently used code version
+    pub fn get_active_codeversion(&self) -> Result<String, String> {
+        match self.webdav.get(self.path("/.version").as_str()) {
+            Ok(mut result) => {
+                match self.parse_response_status(&mut result, "wrong username or password!") {
+                    Ok(()) => Ok(
+                        result.text().unwrap()
+                        .split("\n")
+                        .nth(6).unwrap()
+                        .split("/")
+                        .nth(0).unwrap()
+                        .to_owned()
+                    ),
+                    Err(message) => Err(message),
+                }
+            },
+            Err(error) => Err(format!("{}", error))
+        }
+    }
+
+    /// Checks if the Sandbox answers at all, any response counts even an error status
+    pub fn is_reachable(&self) -> bool {
+        self.webdav.get(self.path("/.version").as_str()).is_ok()
+    }
+
+    /// Sends any type of file ( .txt, .json, .zip ...) to Sandbox
+    /// collection should be any type that reqwest accepts as a Body
+    /// remote_path must be relative path on Sandbox to the current active code version including the file name and extension
+    pub fn send_collection<B: Into<Body>>(&self, collection: B, remote_path: &str) -> Result<(), String> {
+        match self.webdav.put(collection, self.path_with_version(remote_path).as_ref()) {
+            Ok(mut result) => self.parse_response_status(&mut result, "send file"),
+            Err(e) => Err(format!("Error sending file to Sandbox: {}\nRemote path: {}", e, remote_path)),
+        }
+    }
+
+    pub fn unzip_remote_zip(&self, path: &str) -> Result<(), Error> {
+        match self.webdav.unzip(self.path_with_version(path).as_str()) {
+            Ok(result) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deletes collections, files or folders from Webdav server
+    /// If the folder contains other folders or files they will be also deleted
+    pub fn delete_remote_collection(&self, path: &str) -> Result<(), String> {
+        match self.webdav.delete(self.path_with_version(path).as_str()) {
+            Ok(mut result) => self.parse_response_status(&mut result, "delete remote file"),
+            Err(e) => Err(format!("Error deleting remote file: {}\nRemote path: {}", e, path)),
+        }
+    }
+
+    pub fn create_dir(&self, path: &str) -> Result<(), String> {
+        match self.webdav.mkcol(self.path_with_version(path).as_str()) {
+            Ok(mut result) => self.parse_response_status(&mut result, "create folder"),
+            Err(e) => Err("Error creating directory!".to_owned()),
+        }
+    }
+
+    /// Creates an empty code version next to the active one
+    pub fn create_code_version(&self, code_version: &str) -> Result<(), String> {
+        match self.webdav.mkcol(self.path(format!("/{}", code_version).as_str()).as_str()) {
+            Ok(mut result) => self.parse_response_status(&mut result, "create code version"),
+            Err(e) => Err(format!("Error creating code version: {}\nCode version: {}", e, code_version)),
+        }
+    }
+
+    pub fn get_code_version(&self) -> String {
+        self.code_version.to_owned()
+    }
+
+    /// Remote paths are relative to this code version from now on
+    pub fn set_code_version(&mut self, code_version: &str) -> () {
+        self.code_version = code_version.to_owned();
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), String> {
+        match self.webdav.mv(self.path_with_version(from).as_str(), self.path_with_version(to).as_str()) {
+            Ok(mut result) => self.parse_response_status(&mut result, "rename collection"),
+            Err(e) => Err(format!("Error renaming collection: {}!\nFrom: {}\nTo: {}", e, from, to)),
+        }
+    }
+
+    /// Lists the remote files under the folder with their size
+    /// Paths are relative to the code version (e.g. /app_core/cartridge/file.js)
+    pub fn list_files(&self, path: &str) -> Result<BTreeMap<String, u64>, String> {
+        let prefix = format!("/Cartridges/{}", self.code_version);
+        let mut files: BTreeMap<String, u64> = BTreeMap::new();
+        let mut listed: Vec<String> = vec![];
+        let mut folders: Vec<String> = vec![path.trim_end_matches("/").to_owned()];
+
+        while let Some(folder) = folders.pop() {
+            let mut result = self.webdav.ls(self.path_with_version(&folder).as_str())
+                .map_err(|e| format!("Error listing remote folder: {}\nRemote path: {}", e, folder))?;
+            // PROPFIND answers with a multistatus, any other method reporting 207 partly failed
+            if result.status().as_u16() != 207 {
+                self.parse_response_status(&mut result, "list remote folder")?;
+            }
+            let body = result.text().map_err(|e| format!("Error reading remote listing: {}\nRemote path: {}", e, folder))?;
+            listed.push(folder.clone());
+
+            for (href, size, is_collection) in parse_multistatus(&body).into_iter() {
+                let rel_path = match href.find(&prefix) {
+                    Some(index) => href[index + prefix.len()..].trim_end_matches("/").to_owned(),
+                    None => continue,
+                };
+
+                if is_collection {
+                    // servers answering with Depth infinity already listed the subfolders
+                    if !listed.contains(&rel_path) && !folders.contains(&rel_path) {
+                        folders.push(rel_path);
+                    }
+                } else {
+                    files.insert(rel_path, size);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Replays the operations of a change set, e.g. one saved to disk earlier
+    /// Returns the failed operations together with the error message
+    /// and the stat and hash of the uploaded files as they were sent
+    pub fn push_change_set(&self, change_set: &lot::ChangeSet) -> (Vec<(lot::Operation, String)>, Uploaded) {
+        let time = time::Time::new();
+        let mut failed = vec![];
+        let mut uploaded = Uploaded::new();
+
+        for stage in change_set.stages.iter() {
+            let (uploads, others): (Vec<&lot::Operation>, Vec<&lot::Operation>) = stage.iter()
+                .partition(|operation| match operation {
+                    lot::Operation::Upload(_) => true,
+                    _ => false,
+                });
+
+            // deletes and renames always run one by one
+            for operation in others.into_iter() {
+                if let Err(message) = self.push_operation(operation, &time, &mut uploaded) {
+                    failed.push((operation.clone(), message));
+                }
+            }
+
+            if self.is_large(&uploads) {
+                match self.push_zipped(&uploads, &time, &mut uploaded) {
+                    Ok(()) => continue,
+                    Err(message) => println!("{}, pushing the files one by one", message),
+                }
+            }
+
+            for operation in uploads.into_iter() {
+                if let Err(message) = self.push_operation(operation, &time, &mut uploaded) {
+                    failed.push((operation.clone(), message));
+                }
+            }
+        }
+
+        (failed, uploaded)
+    }
+
+    fn is_large(&self, uploads: &Vec<&lot::Operation>) -> bool {
+        let size: u64 = uploads.iter()
+            .filter_map(|operation| match operation {
+                lot::Operation::Upload(file) => std::fs::metadata(&file.full_path).ok(),
+                _ => None,
+            })
+            .map(|metadata| metadata.len())
+            .sum();
+
+        is_worth_zipping(uploads.len(), size, self.zip_min_files, self.zip_min_bytes)
+    }
+
+    /// Sends the files as one zip and unzips it into the code version
+    /// Fails if a file cannot be read so the caller can push the files one by one instead
+    fn push_zipped(&self, uploads: &Vec<&lot::Operation>, time: &time::Time, uploaded: &mut Uploaded) -> Result<(), String> {
+        let mut files: Vec<(String, Vec<u8>)> = vec![];
+        let mut read = Uploaded::new();
+
+        for operation in uploads.iter() {
+            if let lot::Operation::Upload(file) = operation {
+                let (bytes, stat, hash) = read_for_upload(Path::new(&file.full_path))?;
+                files.push((file.rel_path.trim_start_matches("/").to_owned(), bytes));
+                read.insert(file.rel_path.clone(), (stat, hash));
+            }
+        }
+
+        let zip = archive::zip_files(&files)?;
+        let remote_zip_path = format!("/batch-{}.zip", time.timestamp());
+
+        self.send_collection(zip, remote_zip_path.as_str())?;
+        let unzipped = self.unzip_remote_zip(remote_zip_path.as_str())
+            .map_err(|e| format!("Unable to unzip {}: {}", remote_zip_path, e));
+        if let Err(message) = self.delete_remote_collection(remote_zip_path.as_str()) {
+            println!("{} at path: {}", message, remote_zip_path);
+        }
+        unzipped?;
+
+        let current = time.current();
+        files.iter().for_each(|(name, _)| println!("[Z {}] /{}", current.get_time(), name));
+        uploaded.extend(read);
+
+        Ok(())
+    }
+
+    fn push_operation(&self, operation: &lot::Operation, time: &time::Time, uploaded: &mut Uploaded) -> Result<(), String> {
+        match operation {
+            lot::Operation::Rename(rename) => {
+                let result = self.rename(rename.current.as_str(), rename.new.as_str());
+
+                match &result {
+                    Ok(()) => println!("[R {}] from: {} to: {}", time.current().get_time(), rename.current, rename.new),
+                    Err(message) => println!("[R {}] {}", time.current().get_time(), message),
+                }
+
+                result
+            },
+            lot::Operation::Upload(file) => {
+                let (bytes, stat, hash) = match read_for_upload(Path::new(&file.full_path)) {
+                    Ok(read) => read,
+                    Err(message) => {
+                        println!("{}", message);
+                        return Err(message);
+                    },
+                };
+
+                let result = self.send_collection(bytes, file.rel_path.as_str());
+                let current = time.current();
+
+                match &result {
+                    Ok(()) => {
+                        println!("[U {}] {}", current.get_time(), file.rel_path);
+                        uploaded.insert(file.rel_path.clone(), (stat, hash));
+                    },
+                    Err(message) => println!("{}", message),
+                }
+
+                result
+            },
+            lot::Operation::Remove(path) => {
+                let result = self.delete_remote_collection(path);
+                let current = time.current();
+                match &result {
+                    Ok(()) => println!("[D {}] {}", current.get_time(), path),
+                    Err(message) => println!("{} at path: {}", message, path),
+                }
+
+                result
+            },
+        }
+    }
+}
+
+/// Many small requests are slower than one zip unzipped on the Sandbox
+/// A single file is sent as it is however large it is
+fn is_worth_zipping(count: usize, size: u64, min_files: usize, min_bytes: u64) -> bool {
+    count >= min_files || (count > 1 && size >= min_bytes)
+}
+
+/// Reads the href, size and collection flag of every response of a PROPFIND answer
+fn parse_multistatus(body: &str) -> Vec<(String, u64, bool)> {
+    let response = Regex::new(r"(?s)<(?:\w+:)?response[^>]*>(.*?)</(?:\w+:)?response>").unwrap();
+    let href = Regex::new(r"(?s)<(?:\w+:)?href[^>]*>(.*?)</(?:\w+:)?href>").unwrap();
+    let length = Regex::new(r"<(?:\w+:)?getcontentlength[^>]*>\s*(\d+)\s*<").unwrap();
+    let collection = Regex::new(r"<(?:\w+:)?collection\s*/>").unwrap();
+
+    response.captures_iter(body)
+        .filter_map(|captures| {
+            let content = captures.get(1)?.as_str();
+            let path = percent_decode(href.captures(content)?.get(1)?.as_str().trim());
+            let size = length.captures(content)
+                .and_then(|size| size[1].parse::<u64>().ok())
+                .unwrap_or(0);
+
+            Some((path, size, collection.is_match(content)))
+        })
+        .collect()
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let hex = if bytes[index] == b'%' && index + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[index + 1..index + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests;
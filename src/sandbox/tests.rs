@@ -0,0 +1,40 @@
+use super::{Sandbox, is_worth_zipping};
+use super::super::json;
+use super::super::lot::{Data, ChangeSet};
+
+#[test]
+fn uploads_are_zipped_from_enough_files_or_bytes() {
+    assert!(!is_worth_zipping(0, 0, 3, 100));
+    assert!(!is_worth_zipping(2, 99, 3, 100));
+    assert!(is_worth_zipping(3, 0, 3, 100));
+    assert!(is_worth_zipping(2, 100, 3, 100));
+
+    // a single file is never zipped, even with a threshold of one file
+    assert!(!is_worth_zipping(1, 1000, 3, 100));
+    assert!(is_worth_zipping(1, 0, 1, 100));
+}
+
+#[test]
+fn unreadable_files_are_pushed_one_by_one() {
+    let sandbox = Sandbox::init(&json::parse_config(r#"{
+        "hostname": "sandbox.invalid",
+        "username": "user",
+        "password": "password",
+        "cartridges_path": "/base",
+        "code_version": "version1",
+        "zip_min_files": 2
+    }"#));
+
+    // the files are gone before the batch is read, the zip is never built or sent
+    let base = std::env::temp_dir().join(format!("rustyuploader-sandbox-missing-{}", std::process::id()));
+    let mut data = Data::new(base.to_str().unwrap());
+    data.push_write(base.join("app/a.js"));
+    data.push_write(base.join("app/b.js"));
+    let change_set = ChangeSet::new(&data);
+    assert_eq!(change_set.len(), 2);
+
+    let (failed, uploaded) = sandbox.push_change_set(&change_set);
+    assert_eq!(failed.len(), 2);
+    assert!(failed.iter().all(|(_, message)| message.starts_with("Unable to open file")));
+    assert!(uploaded.is_empty());
+}
@@ -0,0 +1,92 @@
+extern crate ctrlc;
+
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::thread;
+
+/// Exit code used when the session was stopped by SIGINT or SIGTERM
+pub const EXIT_INTERRUPTED: i32 = 130;
+/// Exit code used when some operations failed
+pub const EXIT_FAILED: i32 = 1;
+
+/// Handles SIGINT and SIGTERM
+/// The first signal asks the running operations to stop safely, the second one exits immediately
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Installs the signal handler, it can be installed only once per process
+    pub fn install() -> Result<Self, String> {
+        let requested = Arc::new(AtomicBool::new(false));
+        let signals = Arc::new(AtomicUsize::new(0));
+        let handler_requested = Arc::clone(&requested);
+
+        let result = ctrlc::set_handler(move || {
+            if signals.fetch_add(1, Ordering::SeqCst) > 0 {
+                println!("\nForced exit");
+                process::exit(EXIT_INTERRUPTED);
+            }
+
+            println!("\nStopping after the current step, press Ctrl-C again to force exit");
+            handler_requested.store(true, Ordering::SeqCst);
+        });
+
+        match result {
+            Ok(()) => Ok(Shutdown { requested: requested }),
+            Err(e) => Err(format!("Unable to handle signals: {}", e)),
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until a signal is received
+    pub fn wait(&self) -> () {
+        while !self.is_requested() {
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Counts of a push or watch session printed when it ends
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub pushed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub interrupted: bool,
+    started: Instant,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary { pushed: 0, failed: 0, skipped: 0, interrupted: false, started: Instant::now() }
+    }
+
+    pub fn print(&self) -> () {
+        println!(
+            "Session summary: {} pushed, {} failed, {} skipped in {}s{}",
+            self.pushed,
+            self.failed,
+            self.skipped,
+            self.started.elapsed().as_secs(),
+            if self.interrupted { " (interrupted)" } else { "" },
+        );
+    }
+
+    /// 0 when everything was pushed, EXIT_FAILED if anything failed, EXIT_INTERRUPTED otherwise when stopped by a signal
+    pub fn exit_code(&self) -> i32 {
+        if self.failed > 0 {
+            EXIT_FAILED
+        } else if self.interrupted {
+            EXIT_INTERRUPTED
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,88 @@
+extern crate walkdir;
+
+use walkdir::{WalkDir, DirEntry};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How symbolic links inside the cartridges are zipped, watched and compared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Symlinks {
+    /// Links to files and folders are followed, loops are skipped with a warning
+    Follow,
+    /// Links to files are read as the content of their target, links to folders are skipped with a warning
+    Content,
+    /// Every link is skipped with a warning
+    Skip,
+}
+
+impl Symlinks {
+    /// Unknown names (e.g. "Follow", "skp") fall back to content with a warning
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "follow" => Symlinks::Follow,
+            "content" => Symlinks::Content,
+            "skip" => Symlinks::Skip,
+            _ => {
+                println!("Unknown symlinks policy: {:?}, expected follow, content or skip. Using content", name);
+                Symlinks::Content
+            },
+        }
+    }
+
+    /// Walks the folder following the links only when the policy says so
+    /// walkdir detects the loops and reports them as errors, see is_loop
+    pub fn walk(&self, root: &Path) -> WalkDir {
+        WalkDir::new(root).follow_links(*self == Symlinks::Follow)
+    }
+
+    /// Checks an entry of a walk started with walk
+    pub fn accepts(&self, entry: &DirEntry) -> bool {
+        !entry.path_is_symlink() || self.accepts_link(entry.path())
+    }
+
+    /// Same check for a path reported by the watcher
+    /// Paths that are gone are accepted so their removal is still pushed
+    pub fn accepts_path(&self, path: &Path) -> bool {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => self.accepts_link(path),
+            _ => true,
+        }
+    }
+
+    fn accepts_link(&self, path: &Path) -> bool {
+        match self {
+            Symlinks::Follow => true,
+            Symlinks::Content => path.is_file(),
+            Symlinks::Skip => false,
+        }
+    }
+
+    /// Accepted links under the folder (the folder itself included) as pairs of the resolved target and the link
+    /// Native watchers do not follow links so their targets have to be watched on their own
+    pub fn links(&self, root: &Path) -> Vec<(PathBuf, PathBuf)> {
+        if *self == Symlinks::Skip {
+            return vec![];
+        }
+
+        self.walk(root).into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path_is_symlink() && self.accepts(entry))
+            .filter_map(|entry| fs::canonicalize(entry.path()).ok().map(|target| (target, entry.path().to_path_buf())))
+            .collect()
+    }
+}
+
+impl Default for Symlinks {
+    fn default() -> Self {
+        Symlinks::Content
+    }
+}
+
+/// Links followed back to one of their parents
+pub fn is_loop(error: &walkdir::Error) -> bool {
+    error.loop_ancestor().is_some()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::Symlinks;
+
+fn temp_folder(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustyuploader-symlinks-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+
+    path
+}
+
+#[test]
+fn unknown_names_fall_back_to_content() {
+    assert_eq!(Symlinks::from_name("follow"), Symlinks::Follow);
+    assert_eq!(Symlinks::from_name("skip"), Symlinks::Skip);
+    assert_eq!(Symlinks::from_name("content"), Symlinks::Content);
+    assert_eq!(Symlinks::from_name("Follow"), Symlinks::Content);
+    assert_eq!(Symlinks::from_name("skp"), Symlinks::Content);
+}
+
+#[cfg(unix)]
+#[test]
+fn accepts_path_by_policy() {
+    use std::os::unix::fs::symlink;
+
+    let base = temp_folder("accepts");
+    fs::create_dir_all(base.join("shared")).unwrap();
+    fs::write(base.join("shared/readme.md"), "shared").unwrap();
+    fs::write(base.join("file.js"), "file").unwrap();
+    symlink(base.join("shared"), base.join("folder_link")).unwrap();
+    symlink(base.join("shared/readme.md"), base.join("file_link.md")).unwrap();
+    symlink(base.join("missing.md"), base.join("broken_link.md")).unwrap();
+
+    let accepted = |symlinks: Symlinks| -> Vec<bool> {
+        ["file.js", "shared", "folder_link", "file_link.md", "broken_link.md", "gone.js"].iter()
+            .map(|name| symlinks.accepts_path(&base.join(name)))
+            .collect()
+    };
+
+    assert_eq!(accepted(Symlinks::Follow), vec![true, true, true, true, true, true]);
+    assert_eq!(accepted(Symlinks::Content), vec![true, true, false, true, false, true]);
+    assert_eq!(accepted(Symlinks::Skip), vec![true, true, false, false, false, true]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -17,8 +17,18 @@ impl Time {
         }
     }
 
+    /// Seconds since the unix epoch
+    pub fn timestamp(&self) -> u64 {
+        time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    /// Milliseconds since the unix epoch
+    pub fn timestamp_millis(&self) -> u128 {
+        time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_millis()
+    }
+
     pub fn current(&self) -> Present {
-        let system_time = time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap().as_secs();
+        let system_time = self.timestamp();
         let today_seconds = system_time % (self.as_days);
         Present {
             hours: today_seconds / 60 / 60,
@@ -0,0 +1,1189 @@
+use std::thread;
+use std::sync::{mpsc, Mutex, Arc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::cmp;
+use std::fs;
+
+use super::json;
+use super::loader;
+use super::watch;
+use super::lot;
+use super::sandbox;
+use super::time;
+use super::archive::*;
+use super::filter::Filter;
+use super::event::FileEvent;
+use super::manifest::{Manifest, Stat, Uploaded};
+use super::git;
+use super::symlinks::Symlinks;
+use super::queue::{Queue, Backoff};
+use super::shutdown::{Shutdown, Summary, EXIT_FAILED};
+
+/// State of a running watch
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    pub paused: bool,
+    /// Collected operations waiting for the next batch
+    pub pending: usize,
+    /// Operations of the batch being uploaded
+    pub uploading: usize,
+    /// End of the last batch pushed without failures
+    pub last_sync: Option<SystemTime>,
+    pub last_error: Option<String>,
+    /// Operations pushed since the watch started
+    pub pushed: usize,
+    /// Operations that failed since the watch started
+    pub failed: usize,
+    /// Operations waiting for the Sandbox to be reachable again
+    pub queued: usize,
+    /// Uploads dropped because the content did not change
+    pub skipped: usize,
+}
+
+/// Shared between the WatchHandle and the watch threads
+struct Control {
+    stop: AtomicBool,
+    pause: AtomicBool,
+    flush: AtomicBool,
+    discard: AtomicBool,
+    busy: AtomicBool,
+    /// Number of batches handed to the upload thread
+    batches: AtomicUsize,
+    status: Mutex<Status>,
+}
+
+impl Control {
+    fn new() -> Self {
+        Control {
+            stop: AtomicBool::new(false),
+            pause: AtomicBool::new(false),
+            flush: AtomicBool::new(false),
+            discard: AtomicBool::new(false),
+            busy: AtomicBool::new(false),
+            batches: AtomicUsize::new(0),
+            status: Mutex::new(Status::default()),
+        }
+    }
+}
+
+/// Controls a watch running in the background
+#[must_use = "dropping the handle stops the watch"]
+pub struct WatchHandle {
+    control: Arc<Control>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    /// Pushes the pending changes and waits for the watch to finish
+    pub fn stop(mut self) -> () {
+        self.control.stop.store(true, Ordering::SeqCst);
+        self.join_thread();
+    }
+
+    /// Drops the pending changes and waits for the batch being uploaded
+    pub fn discard(mut self) -> () {
+        self.control.discard.store(true, Ordering::SeqCst);
+        self.control.stop.store(true, Ordering::SeqCst);
+        self.join_thread();
+    }
+
+    /// Blocks until the watch ends on its own (e.g. the watcher fails)
+    pub fn join(mut self) -> () {
+        self.join_thread();
+    }
+
+    /// Changes are collected but not pushed until resume
+    pub fn pause(&self) -> () {
+        self.control.pause.store(true, Ordering::SeqCst);
+        self.control.status.lock().unwrap().paused = true;
+    }
+
+    pub fn resume(&self) -> () {
+        self.control.pause.store(false, Ordering::SeqCst);
+        self.control.status.lock().unwrap().paused = false;
+    }
+
+    /// Pushes the collected changes without waiting for the quiet period
+    pub fn flush_now(&self) -> () {
+        self.control.flush.store(true, Ordering::SeqCst);
+    }
+
+    pub fn status(&self) -> Status {
+        self.control.status.lock().unwrap().clone()
+    }
+
+    /// False once the watch ended, stopped through the handle or on its own
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().map(|thread| !thread.is_finished()).unwrap_or(false)
+    }
+
+    fn join_thread(&mut self) -> () {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Dropping the handle stops the watch like stop, the pending changes are pushed first
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.control.stop.store(true, Ordering::SeqCst);
+        self.join_thread();
+    }
+}
+
+#[derive(Clone)]
+pub struct Uploader {
+    config: json::Config,
+    /// Folder of the config file, the default location of the files kept between runs
+    config_dir: PathBuf,
+    cartridges: Vec<String>,
+    ignore_list: Vec<String>,
+    filter: Filter,
+    dry_run: bool,
+    arc_sandbox: Arc<Mutex<sandbox::Sandbox>>,
+    shutdown: Option<Shutdown>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl Uploader {
+    pub fn new(config_path: &str) -> Self {
+        let config = json::parse_config(&loader::read_file(config_path));
+        let cartridges = if config.get_cartridges().is_empty() {
+            loader::get_watched_cartridges(config.get_cartridges_path().as_str())
+        } else {
+            config.get_cartridges()
+        };
+        let config_dir = Path::new(config_path).parent().unwrap_or(Path::new(""));
+        let queue_path = config.get_queue_path(config_dir);
+        Uploader {
+            config: config.clone(),
+            config_dir: config_dir.to_path_buf(),
+            cartridges: cartridges,
+            ignore_list: config.get_ignore_list(),
+            filter: Filter::everything(),
+            dry_run: false,
+            arc_sandbox: Arc::new(Mutex::new(sandbox::Sandbox::init(&config))),
+            shutdown: None,
+            queue: Arc::new(Mutex::new(Queue::load(queue_path.as_str(), config.get_cartridges_path().as_str()))),
+        }
+    }
+
+    /// Only the files and folders matching the filter are pushed to the Sandbox
+    /// The filter is applied on top of the cartridges and the ignore list
+    pub fn set_filter(&mut self, filter: Filter) -> () {
+        self.filter = filter;
+    }
+
+    /// Prints the operations instead of pushing them to the Sandbox
+    pub fn set_dry_run(&mut self, dry_run: bool) -> () {
+        self.dry_run = dry_run;
+    }
+
+    /// Replays a saved change set against the configured Sandbox
+    /// Uploaded files are read from the configured cartridges path
+    pub fn apply(&self, change_set_path: &str) -> Result<(), String> {
+        let mut change_set = lot::ChangeSet::load(change_set_path)?;
+        change_set.rebase(self.config.get_cartridges_path().as_str());
+
+        if self.dry_run {
+            change_set.describe().iter().for_each(|line| println!("{}", line));
+            return Ok(());
+        }
+
+        self.push_checked(&change_set)
+    }
+
+    /// Pushes the files added, modified, deleted or renamed since the git commit
+    /// Uncommitted and untracked files are included, the cartridges are not zipped
+    pub fn push_since(&self, since: &str) -> Result<(), String> {
+        let cartridges_path = self.config.get_cartridges_path();
+        let mut collection = lot::Collection::init(cartridges_path.as_str(), self.cartridges.clone(), self.ignore_list.clone());
+
+        for event in git::changes_since(Path::new(&cartridges_path), since)?.into_iter() {
+            collection.parse_event(event);
+        }
+
+        let change_set = lot::ChangeSet::new(&collection.get_data(&self.filter));
+        if change_set.is_empty() {
+            println!("Nothing changed since {}", since);
+            return Ok(());
+        }
+
+        if self.dry_run {
+            change_set.describe().iter().for_each(|line| println!("{}", line));
+            return Ok(());
+        }
+
+        self.push_checked(&change_set)
+    }
+
+    /// Pushes behind the offline queue, operations that fail while the Sandbox is unreachable are queued
+    fn push_checked(&self, change_set: &lot::ChangeSet) -> Result<(), String> {
+        let mut backoff = Backoff::new(Duration::from_millis(self.config.get_probe_interval_ms()));
+        let (_, _, failed) = deliver(&self.arc_sandbox.lock().unwrap(), &mut self.queue.lock().unwrap(), &mut backoff, change_set, &self.rename_fallback());
+        if !failed.is_empty() {
+            return Err(format!("{} of {} operations failed", failed.len(), change_set.len()));
+        }
+
+        let queued = self.queue.lock().unwrap().len();
+        if queued > 0 {
+            return Err(format!("Sandbox unreachable, {} operations are queued", queued));
+        }
+
+        Ok(())
+    }
+
+    /// Uploads of the files at the target of a rename, pushed when the Sandbox cannot move the source
+    /// e.g. the source never reached the Sandbox
+    fn rename_fallback(&self) -> impl Fn(&lot::Rename) -> lot::ChangeSet {
+        let cartridges_path = self.config.get_cartridges_path();
+        let ignore_list = self.ignore_list.clone();
+        let filter = self.filter.clone();
+        let symlinks = self.symlinks();
+
+        move |rename: &lot::Rename| {
+            let target = vec![rename.new.trim_start_matches("/").to_owned()];
+            let mut data = lot::Data::new(cartridges_path.as_str());
+            for path in Manifest::scan(cartridges_path.as_str(), &target, &ignore_list, &filter, symlinks).files.keys() {
+                data.push_write(PathBuf::from(format!("{}{}", cartridges_path, path)));
+            }
+
+            lot::ChangeSet::new(&data)
+        }
+    }
+
+    /// Starts watching in the background
+    /// The returned handle stops, pauses and flushes the watcher
+    #[must_use = "dropping the handle stops the watch, see watch_until_signal to block instead"]
+    pub fn watch(&self) -> WatchHandle {
+        let uploader = self.clone();
+        let control = Arc::new(Control::new());
+        let thread_control = Arc::clone(&control);
+
+        let thread = thread::spawn(move || uploader.run_watch(thread_control));
+
+        WatchHandle { control: control, thread: Some(thread) }
+    }
+
+    fn run_watch(&self, control: Arc<Control>) -> () {
+        let cartridges_path = self.config.get_cartridges_path();
+        let cartridges = self.cartridges.clone();
+        let ignore_list = self.ignore_list.clone();
+        let timing = watch::Timing::from_config(&self.config);
+
+        // watcher
+        // watch only the selected cartridges instead of the whole cartridges path
+        let mut targets: Vec<watch::Target> = cartridges.iter()
+            .map(|name| watch::Target::recursive(format!("{}/{}", cartridges_path, name).as_str()))
+            .collect();
+
+        // cartridges are not configured, watch for new cartridge folders as well
+        let discover_cartridges: bool = self.config.get_cartridges().is_empty();
+        if discover_cartridges {
+            targets.push(watch::Target::flat(cartridges_path.as_str()));
+        }
+
+        let symlinks = self.symlinks();
+        let mut watcher = watch::Sentry::spy_all(targets, timing.debounce, &watch::Backend::from_config(&self.config), symlinks);
+
+        // files assumed to be on the Sandbox, used to resync when events are lost
+        // the upload thread records the pushed operations in it
+        let mut local = Manifest::scan(cartridges_path.as_str(), &cartridges, &ignore_list, &self.filter, symlinks);
+        let startup = self.startup_changes(&mut local);
+        let synced = Arc::new(Mutex::new(local));
+
+        // uploader stage, pushes one batch at a time while the next one is collected
+        let (batch_tx, batch_rx) = mpsc::channel::<lot::ChangeSet>();
+        let upload_thread = self.spawn_upload_thread(batch_rx, Arc::clone(&control), Arc::clone(&synced), timing.clone());
+
+        // changes made while the watcher was not running (e.g. a git pull)
+        if let Some(data) = startup {
+            let change_set = lot::ChangeSet::new(&data);
+            if !change_set.is_empty() {
+                println!("[S {}] Startup sync, pushing {} changes", time::Time::new().current().get_time(), change_set.len());
+                control.status.lock().unwrap().uploading = change_set.len();
+                control.busy.store(true, Ordering::SeqCst);
+                synced.lock().unwrap().set_pending(&change_set);
+                let _ = batch_tx.send(change_set);
+            }
+        }
+
+        // collection
+        let mut collection = lot::Collection::init(cartridges_path.as_str(), cartridges, ignore_list);
+        collection.set_synced(synced);
+
+        // commit checked out, a change means a checkout, pull or rebase happened
+        let mut git_head: Option<String> = if self.config.get_git_sync() {
+            git::head(Path::new(&cartridges_path)).ok()
+        } else {
+            None
+        };
+
+        // start of the batch being collected, None while there are no events
+        let mut batch_start: Option<Instant> = None;
+
+        while !control.stop.load(Ordering::SeqCst) {
+            let timeout = match batch_start {
+                // no events yet, wake up regularly to check the handle
+                None => timing.quiet_period,
+                // keep collecting until the watcher is quiet or the batch gets too old
+                Some(start) => {
+                    let age = start.elapsed();
+                    if age >= timing.max_batch_age {
+                        timing.quiet_period
+                    } else {
+                        cmp::min(timing.quiet_period, timing.max_batch_age - age)
+                    }
+                },
+            };
+
+            let flush_now: bool = control.flush.load(Ordering::SeqCst);
+
+            match watcher.recv_timeout(timeout) {
+                Ok(event) => {
+                    if discover_cartridges {
+                        self.discover_cartridge(&mut watcher, &mut collection, &event);
+                    }
+                    if let FileEvent::Create(path) = &event.event {
+                        watcher.watch_links(path);
+                    }
+                    // links left out by the symlink policy never reach the Sandbox
+                    if let Some(path) = event.event.path().filter(|path| !symlinks.accepts_path(path)) {
+                        println!("Skipping symbolic link {:?}", path);
+                        continue;
+                    }
+                    collection.parse_event(event.event);
+                    control.status.lock().unwrap().pending = collection.data.len();
+
+                    let start = *batch_start.get_or_insert(Instant::now());
+                    if start.elapsed() < timing.max_batch_age && !flush_now {
+                        continue;
+                    }
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) if batch_start.is_none() && !flush_now => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(e) => {
+                    println!("Error listening for events: {}", e);
+                    break;
+                },
+            }
+
+            // changes are buffered while paused (e.g. during a git rebase)
+            if control.pause.load(Ordering::SeqCst) && !flush_now {
+                continue;
+            }
+
+            // e.g. it panicked, the busy flag is never cleared
+            if upload_thread.is_finished() {
+                println!("Upload thread stopped");
+                break;
+            }
+
+            // the previous batch is still uploading, keep coalescing events into this one
+            // so a path is never pushed again while its upload is in flight
+            if control.busy.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            control.flush.store(false, Ordering::SeqCst);
+            batch_start = None;
+
+            self.sync_checkout(&mut git_head, &mut collection);
+            if !self.hand_off(&mut collection, &batch_tx, &control) {
+                break;
+            }
+        }
+
+        // push what is still pending before stopping
+        // the upload thread takes the batches in order, this one is pushed after the one in flight
+        if batch_start.is_some() && !control.discard.load(Ordering::SeqCst) {
+            self.sync_checkout(&mut git_head, &mut collection);
+            self.hand_off(&mut collection, &batch_tx, &control);
+        }
+
+        drop(batch_tx);
+        let _ = upload_thread.join();
+    }
+
+    /// Adds the git changes to the collected events when HEAD moved since the last batch
+    /// A checkout produces events the watcher can miss or mix up, git knows them as a short list of changes
+    /// Paths changed both ways are coalesced by the collection, a pending rescan is kept
+    fn sync_checkout(&self, git_head: &mut Option<String>, collection: &mut lot::Collection) -> () {
+        let cartridges_path = self.config.get_cartridges_path();
+        let head = match git_head.as_ref() {
+            Some(head) => head.clone(),
+            None => return,
+        };
+
+        let current = match git::head(Path::new(&cartridges_path)) {
+            Ok(current) if current != head => current,
+            _ => return,
+        };
+
+        match git::changes_since(Path::new(&cartridges_path), head.as_str()) {
+            Ok(events) => {
+                println!("[S {}] HEAD moved to {}, pushing the git changes", time::Time::new().current().get_time(), &current[..cmp::min(7, current.len())]);
+                for event in events.into_iter() {
+                    collection.parse_event(event);
+                }
+            },
+            Err(message) => println!("{}", message),
+        }
+
+        *git_head = Some(current);
+    }
+
+    /// Sends the collected data to the upload thread
+    /// Returns false if the upload thread is gone
+    fn hand_off(&self, collection: &mut lot::Collection, batch_tx: &mpsc::Sender<lot::ChangeSet>, control: &Control) -> bool {
+        let change_set = match self.prepare_batch(collection, control) {
+            Some(change_set) => change_set,
+            None => return true,
+        };
+
+        {
+            let mut status = control.status.lock().unwrap();
+            status.pending = 0;
+            status.uploading = change_set.len();
+        }
+
+        if let Some(synced) = collection.get_synced() {
+            synced.lock().unwrap().set_pending(&change_set);
+        }
+        control.busy.store(true, Ordering::SeqCst);
+        if batch_tx.send(change_set).is_err() {
+            println!("Upload thread stopped");
+            return false;
+        }
+
+        true
+    }
+
+    /// Takes the collected data as a change set
+    /// Returns None when there is nothing to push
+    fn prepare_batch(&self, collection: &mut lot::Collection, control: &Control) -> Option<lot::ChangeSet> {
+        let cartridges_path = self.config.get_cartridges_path();
+        let mut data = collection.get_data(&self.filter);
+
+        // the watcher lost events (e.g. inotify queue overflow), compare every file instead
+        if collection.take_rescan() {
+            let local = Manifest::scan(cartridges_path.as_str(), collection.get_cartridges(), &self.ignore_list, &self.filter, self.symlinks());
+            data = collection.get_synced().unwrap().lock().unwrap().diff(&local, cartridges_path.as_str());
+            println!("[S {}] Watcher lost events, resyncing {} local files", time::Time::new().current().get_time(), local.len());
+        }
+
+        // rewritten with the same content (formatters, unchanged webpack chunks)
+        let skipped = collection.get_synced().map(|synced| synced.lock().unwrap().drop_unchanged(&mut data)).unwrap_or(0);
+        if skipped > 0 {
+            println!("[S {}] Skipped {} unchanged files", time::Time::new().current().get_time(), skipped);
+            control.status.lock().unwrap().skipped += skipped;
+        }
+
+        let change_set = lot::ChangeSet::new(&data);
+        if change_set.is_empty() {
+            return None;
+        }
+
+        if let Some(folder) = self.config.get_change_sets_path() {
+            // batches can follow each other within the same millisecond
+            let number = control.batches.fetch_add(1, Ordering::SeqCst);
+            let path = format!("{}/batch-{}-{}.json", folder, time::Time::new().timestamp_millis(), number);
+            if let Err(message) = change_set.save(path.as_str()) {
+                println!("{}", message);
+            }
+        }
+
+        Some(change_set)
+    }
+
+    /// Local files that differ from the saved manifest or from the remote files, depending on startup_sync
+    /// Content hashes saved with the manifest are kept for the session
+    /// The changed files keep their known state in the local manifest until they are pushed
+    fn startup_changes(&self, local: &mut Manifest) -> Option<lot::Data> {
+        let cartridges_path = self.config.get_cartridges_path();
+        let mode = self.config.get_startup_sync()?;
+
+        let known = match mode.as_str() {
+            "manifest" => Manifest::load(self.config.get_manifest_path(&self.config_dir).as_str()),
+            "remote" => self.remote_manifest(),
+            _ => Err(format!("Unknown startup sync: {}", mode)),
+        };
+
+        match known {
+            Ok(mut known) => {
+                known.restrict(&self.cartridges, &self.ignore_list, &self.filter);
+                let mut data = if mode == "remote" {
+                    known.diff_sizes(local, cartridges_path.as_str())
+                } else {
+                    known.diff(local, cartridges_path.as_str())
+                };
+
+                known.drop_unchanged(&mut data);
+                local.revert(&known, &data);
+                local.hashes = known.hashes;
+
+                Some(data)
+            },
+            Err(message) => {
+                println!("Startup sync skipped: {}", message);
+                None
+            },
+        }
+    }
+
+    /// Remote files of the cartridges, the modification times are unknown
+    fn remote_manifest(&self) -> Result<Manifest, String> {
+        let sandbox = self.arc_sandbox.lock().unwrap();
+        let mut manifest = Manifest::new();
+
+        for cartridge in self.cartridges.iter() {
+            for (path, size) in sandbox.list_files(format!("/{}", cartridge).as_str())?.into_iter() {
+                manifest.files.insert(path, Stat { size: size, modified: 0 });
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// The manifest is kept between runs only when the startup sync uses it
+    fn save_manifest(&self, manifest: &Manifest) -> () {
+        if let Some(path) = self.manifest_path() {
+            save_manifest_at(manifest, path.as_str());
+        }
+    }
+
+    fn manifest_path(&self) -> Option<String> {
+        if self.dry_run || self.config.get_startup_sync().is_none() {
+            return None;
+        }
+
+        Some(self.config.get_manifest_path(&self.config_dir))
+    }
+
+    /// Pushes the batches and records the operations that reached the Sandbox in the synced manifest
+    fn spawn_upload_thread(&self, batch_rx: mpsc::Receiver<lot::ChangeSet>, control: Arc<Control>, synced: Arc<Mutex<Manifest>>, timing: watch::Timing) -> thread::JoinHandle<()> {
+        let arc_sandbox = Arc::clone(&self.arc_sandbox);
+        let queue = Arc::clone(&self.queue);
+        let dry_run = self.dry_run;
+        let probe_interval = Duration::from_millis(self.config.get_probe_interval_ms());
+        let manifest_path = self.manifest_path();
+        let fallback = self.rename_fallback();
+
+        // the pending paths are synced only as far as the push succeeded
+        let record = move |pushed: &lot::ChangeSet, uploaded: &Uploaded| {
+            let mut synced = synced.lock().unwrap();
+            synced.clear_pending();
+
+            if !pushed.is_empty() {
+                synced.record(pushed, uploaded);
+                if let Some(path) = manifest_path.as_ref() {
+                    save_manifest_at(&synced, path.as_str());
+                }
+            }
+        };
+
+        thread::spawn(move || {
+            let mut backoff = Backoff::new(probe_interval);
+
+            // operations queued by a previous run
+            if !dry_run {
+                let (pushed, uploaded, failed) = replay(&arc_sandbox.lock().unwrap(), &mut queue.lock().unwrap(), &mut backoff, &fallback);
+                record(&pushed, &uploaded);
+                update_status(&control, pushed.len(), &failed, queue.lock().unwrap().len());
+            }
+
+            loop {
+                let change_set = match batch_rx.recv_timeout(probe_interval) {
+                    Ok(change_set) => change_set,
+                    // probe the Sandbox while operations are queued, less often while it stays unreachable
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        if !dry_run && !queue.lock().unwrap().is_empty() {
+                            let (pushed, uploaded, failed) = replay(&arc_sandbox.lock().unwrap(), &mut queue.lock().unwrap(), &mut backoff, &fallback);
+                            record(&pushed, &uploaded);
+                            update_status(&control, pushed.len(), &failed, queue.lock().unwrap().len());
+                        }
+                        continue;
+                    },
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+
+                // wait for half written files (e.g. webpack bundles) before pushing them
+                if let Some(interval) = timing.settle {
+                    let paths: Vec<String> = change_set.stages.iter()
+                        .flat_map(|stage| stage.iter())
+                        .filter_map(|operation| match operation {
+                            lot::Operation::Upload(file) => Some(file.full_path.clone()),
+                            _ => None,
+                        })
+                        .collect();
+
+                    if !watch::settle(&paths, interval, timing.max_batch_age) {
+                        println!("Files are still changing, pushing them anyway");
+                    }
+                }
+
+                let (pushed, uploaded, failed) = if dry_run {
+                    change_set.describe().iter().for_each(|line| println!("{}", line));
+                    (lot::ChangeSet::empty(change_set.base_path.as_str()), Uploaded::new(), vec![])
+                } else {
+                    deliver(&arc_sandbox.lock().unwrap(), &mut queue.lock().unwrap(), &mut backoff, &change_set, &fallback)
+                };
+
+                record(&pushed, &uploaded);
+                update_status(&control, pushed.len(), &failed, queue.lock().unwrap().len());
+                {
+                    // failed and queued operations are missing from the pushed ones
+                    let mut status = control.status.lock().unwrap();
+                    status.uploading = 0;
+                    if !dry_run && pushed.len() == change_set.len() {
+                        status.last_sync = Some(SystemTime::now());
+                    }
+                }
+
+                control.busy.store(false, Ordering::SeqCst);
+            }
+        })
+    }
+
+    // starts watching a cartridge folder created directly under the cartridges path
+    // and stops watching the ones removed or moved away
+    fn discover_cartridge(&self, watcher: &mut watch::Sentry, collection: &mut lot::Collection, event: &watch::Event) -> () {
+        let cartridges_path = PathBuf::from(self.config.get_cartridges_path());
+        let is_cartridge = |path: &PathBuf| path.parent() == Some(cartridges_path.as_path());
+
+        match &event.event {
+            FileEvent::Remove(path) | FileEvent::Rename(path, _) if is_cartridge(path) && watcher.is_watched(path) => {
+                if let Err(message) = watcher.remove(path) {
+                    println!("{}", message);
+                }
+                if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                    println!("[{}] Cartridge removed", name);
+                    collection.remove_cartridge(name);
+                }
+            },
+            _ => (),
+        }
+
+        match &event.event {
+            // reported by the flat watch of the cartridges path
+            FileEvent::Create(path) | FileEvent::Rename(_, path) if event.root.as_ref() == Some(&cartridges_path) && path.is_dir() && is_cartridge(path) => {
+                let name = path.file_name().and_then(|name| name.to_str()).map(|name| name.to_owned());
+
+                if let (Some(name), Some(path)) = (name, path.to_str()) {
+                    match watcher.add(watch::Target::recursive(path)) {
+                        Ok(()) => {
+                            println!("[{}] Watching new cartridge", name);
+                            collection.add_cartridge(name.clone());
+                            self.push_cartridge_files(collection, name);
+                        },
+                        Err(message) => println!("{}", message),
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Files copied or moved in together with a new cartridge are not reported one by one
+    fn push_cartridge_files(&self, collection: &mut lot::Collection, name: String) {
+        let cartridges_path = self.config.get_cartridges_path();
+        let scanned = Manifest::scan(cartridges_path.as_str(), &vec![name], &self.ignore_list, &self.filter, self.symlinks());
+
+        for rel_path in scanned.files.keys() {
+            collection.parse_event(FileEvent::Write(PathBuf::from(format!("{}{}", cartridges_path, rel_path))));
+        }
+    }
+
+    fn symlinks(&self) -> Symlinks {
+        Symlinks::from_name(self.config.get_symlinks().as_str())
+    }
+
+    // returns active code version from the Sandbox
+    pub fn get_active_codeversion(&self) -> Result<String, String> {
+        self.arc_sandbox.lock().unwrap().get_active_codeversion()
+    }
+
+    // pushes to sandbox all watched files
+    /// Pushes every cartridge as a zip
+    /// On SIGINT/SIGTERM (see handle_signals) the current cartridge is finished before stopping
+    pub fn push_all_files(&self) -> Summary {
+        let cartridges_path: String = self.config.get_cartridges_path();
+        let filter: Filter = Filter::exclude(&self.ignore_list).and(self.filter.clone());
+        let mut summary = Summary::new();
+
+        for collection_name in self.cartridges.iter() {
+            if self.is_shutting_down() {
+                summary.interrupted = true;
+                summary.skipped += 1;
+                continue;
+            }
+
+            let collection_path: String = format!("{}/{}", cartridges_path, collection_name);
+            let remote_zip_path = format!("/{}.zip", collection_name);
+            let remote_folder_path = format!("/{}", collection_name);
+
+            println!("[{}] Zipping", collection_name);
+            let collection_zip = match zip_dir(collection_path.as_str(), collection_name, &filter, self.config.get_deterministic_zips(), self.symlinks()) {
+                Ok(collection_zip) => collection_zip,
+                Err(message) => {
+                    println!("[{}] {}", collection_name, message);
+                    summary.failed += 1;
+                    continue;
+                },
+            };
+
+            if self.dry_run {
+                println!("[{}] Dry run, {} files not pushed", collection_name, collection_zip.entries.len());
+                summary.skipped += 1;
+                continue;
+            }
+            self.keep_archive(&collection_zip, collection_name);
+
+            println!("[{}] Deleting remote zip (if any)", collection_name);
+            let _ = self.arc_sandbox.lock().unwrap().delete_remote_collection(remote_zip_path.as_str());
+
+            println!("[{}] Sending zip to remote", collection_name);
+            if let Err(message) = self.arc_sandbox.lock().unwrap().send_collection(collection_zip.bytes, remote_zip_path.as_str()) {
+                // the remote folder is left untouched
+                println!("[{}] {}", collection_name, message);
+                summary.failed += 1;
+                continue;
+            }
+
+            println!("[{}] Deleting remote folder", collection_name);
+            if let Err(message) = self.arc_sandbox.lock().unwrap().delete_remote_collection(remote_folder_path.as_str()) {
+                println!("[{}] {}", collection_name, message);
+            }
+
+            println!("[{}] Unzipping remote zip", collection_name);
+            if let Err(e) = self.arc_sandbox.lock().unwrap().unzip_remote_zip(remote_zip_path.as_str()) {
+                // keep the zip so the cartridge can be restored by unzipping it again
+                println!("[{}] Unable to unzip {}: {}", collection_name, remote_zip_path, e);
+                summary.failed += 1;
+                continue;
+            }
+
+            println!("[{}] Deleting remote zip", collection_name);
+            if let Err(message) = self.arc_sandbox.lock().unwrap().delete_remote_collection(remote_zip_path.as_str()) {
+                println!("[{}] {}", collection_name, message);
+            }
+
+            summary.pushed += 1;
+        }
+
+        if !self.dry_run && summary.pushed == self.cartridges.len() {
+            self.pushed_everything(&filter);
+        }
+
+        summary.print();
+        summary
+    }
+
+    /// Pushes the operations queued while the Sandbox was unreachable
+    /// Returns the number of operations still queued
+    pub fn replay_queue(&self) -> usize {
+        let mut backoff = Backoff::new(Duration::from_millis(self.config.get_probe_interval_ms()));
+        let (_, _, failed) = replay(&self.arc_sandbox.lock().unwrap(), &mut self.queue.lock().unwrap(), &mut backoff, &self.rename_fallback());
+        if !failed.is_empty() {
+            println!("{} queued operations failed", failed.len());
+        }
+
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Operations waiting for the Sandbox to be reachable again
+    pub fn queued(&self) -> Vec<String> {
+        self.queue.lock().unwrap().describe()
+    }
+
+    /// Pushes every cartridge in a single zip laid out at code version level
+    /// It is uploaded, unzipped and deleted once instead of once per cartridge
+    /// With a fresh code version the archive is unzipped into a new, empty code version and nothing is deleted
+    pub fn push_all_files_archive(&self, fresh_code_version: Option<&str>) -> Summary {
+        let cartridges_path: String = self.config.get_cartridges_path();
+        let filter: Filter = Filter::exclude(&self.ignore_list).and(self.filter.clone());
+        let mut summary = Summary::new();
+
+        if self.is_shutting_down() {
+            summary.interrupted = true;
+            summary.skipped = self.cartridges.len();
+            summary.print();
+            return summary;
+        }
+
+        println!("[{} cartridges] Zipping", self.cartridges.len());
+        let archive = match zip_dirs(cartridges_path.as_str(), &self.cartridges, &filter, self.config.get_deterministic_zips(), self.symlinks()) {
+            Ok(archive) => archive,
+            Err(message) => {
+                println!("Unable to zip the cartridges: {}", message);
+                summary.failed = self.cartridges.len();
+                summary.print();
+                return summary;
+            },
+        };
+
+        if self.dry_run {
+            self.cartridges.iter().for_each(|name| println!("[A] /{}", name));
+            println!("[{} cartridges] Dry run, {} files not pushed", self.cartridges.len(), archive.entries.len());
+            summary.skipped = self.cartridges.len();
+            summary.print();
+            return summary;
+        }
+        self.keep_archive(&archive, "cartridges");
+
+        match self.deploy(archive.bytes, &self.cartridges, fresh_code_version) {
+            Ok(()) => summary.pushed = self.cartridges.len(),
+            Err(message) => {
+                println!("{}", message);
+                summary.failed = self.cartridges.len();
+            },
+        }
+
+        // a fresh code version is not the one the queue and the manifest are about
+        if !self.dry_run && fresh_code_version.is_none() && summary.pushed == self.cartridges.len() {
+            self.pushed_everything(&filter);
+        }
+
+        summary.print();
+        summary
+    }
+
+    /// Saves the zip and its manifest in the archives path, if one is configured
+    fn keep_archive(&self, archive: &Archive, name: &str) -> () {
+        if let Some(folder) = self.config.get_archives_path() {
+            if let Err(message) = archive.save(format!("{}/{}.zip", folder, name).as_str()) {
+                println!("{}", message);
+            }
+        }
+    }
+
+    /// Every cartridge was pushed as a whole, nothing queued is missing anymore
+    fn pushed_everything(&self, filter: &Filter) -> () {
+        if !self.queue.lock().unwrap().is_empty() {
+            self.queue.lock().unwrap().take();
+        }
+
+        self.save_manifest(&Manifest::scan(self.config.get_cartridges_path().as_str(), &self.cartridges, &self.ignore_list, filter, self.symlinks()));
+    }
+
+    /// Checks if packing the cartridges now gives the files listed in the archive manifest
+    fn is_local_pack(&self, manifest: &ArchiveManifest, cartridges: &Vec<String>, filter: &Filter) -> bool {
+        match zip_dirs(self.config.get_cartridges_path().as_str(), cartridges, filter, true, self.symlinks()) {
+            Ok(archive) => archive.entries == manifest.files,
+            Err(message) => {
+                println!("{}", message);
+                false
+            }
+        }
+    }
+
+    /// The cartridges were replaced as a whole, queued operations inside them are obsolete
+    /// and their files are synced as they are now, the other cartridges are left alone
+    fn pushed_cartridges(&self, cartridges: &Vec<String>, filter: &Filter) {
+        self.queue.lock().unwrap().drop_under(cartridges);
+
+        if let Some(path) = self.manifest_path() {
+            let mut synced = Manifest::load(path.as_str()).unwrap_or(Manifest::new());
+            synced.replace_cartridges(cartridges, Manifest::scan(self.config.get_cartridges_path().as_str(), cartridges, &self.ignore_list, filter, self.symlinks()));
+            save_manifest_at(&synced, path.as_str());
+        }
+    }
+
+    /// Writes deployable archives of the cartridges to the output folder, using the ignore list and the filter
+    /// One zip per cartridge or a single zip laid out at code version level when combined
+    /// The zips are always deterministic and written with their manifest
+    /// Returns the paths of the written zips
+    pub fn pack(&self, output_path: &str, combined: bool) -> Result<Vec<String>, String> {
+        let cartridges_path: String = self.config.get_cartridges_path();
+        let filter: Filter = Filter::exclude(&self.ignore_list).and(self.filter.clone());
+        let mut written: Vec<String> = vec![];
+
+        fs::create_dir_all(output_path).map_err(|e| format!("Unable to create output folder: {}\nError: {}", output_path, e))?;
+
+        if combined {
+            let path = format!("{}/{}.zip", output_path, self.config.get_code_version());
+            let archive = zip_dirs(cartridges_path.as_str(), &self.cartridges, &filter, true, self.symlinks())?;
+            archive.save(path.as_str())?;
+            written.push(path);
+        } else {
+            for collection_name in self.cartridges.iter() {
+                let path = format!("{}/{}.zip", output_path, collection_name);
+                let archive = zip_dir(format!("{}/{}", cartridges_path, collection_name).as_str(), collection_name, &filter, true, self.symlinks())?;
+                archive.save(path.as_str())?;
+                written.push(path);
+            }
+        }
+
+        written.iter().for_each(|path| println!("[P] {}", path));
+
+        Ok(written)
+    }
+
+    /// Uploads and unzips an archive written by pack, the folders it contains replace the remote ones
+    /// When a manifest was written next to the zip the zip has to match it
+    pub fn deploy_archive(&self, archive_path: &str, fresh_code_version: Option<&str>) -> Result<(), String> {
+        let bytes = fs::read(archive_path).map_err(|e| format!("Unable to read archive at path: {}\nError: {}", archive_path, e))?;
+
+        let manifest_path = format!("{}.manifest.json", archive_path);
+        let manifest = if Path::new(&manifest_path).exists() {
+            let manifest = ArchiveManifest::load(manifest_path.as_str())?;
+            if manifest.sha256 != sha256(&bytes) {
+                return Err(format!("Archive does not match its manifest: {}", archive_path));
+            }
+            Some(manifest)
+        } else {
+            None
+        };
+
+        // the folders are deleted on the Sandbox before unzipping, only known cartridges can be replaced
+        let cartridges = top_level_folders(&bytes)?;
+        if cartridges.is_empty() {
+            return Err(format!("Archive contains no cartridge: {}", archive_path));
+        }
+        if let Some(name) = cartridges.iter().find(|name| !is_cartridge_name(name) || !self.cartridges.contains(name)) {
+            return Err(format!("Archive folder is not a configured cartridge: {:?}", name));
+        }
+
+        if self.dry_run {
+            cartridges.iter().for_each(|name| println!("[A] /{}", name));
+            return Ok(());
+        }
+
+        self.deploy(bytes, &cartridges, fresh_code_version)?;
+
+        // the local files are on the Sandbox only when the archive is what pack gives for them
+        if fresh_code_version.is_none() {
+            let filter = Filter::exclude(&self.ignore_list).and(self.filter.clone());
+            match manifest {
+                Some(manifest) if self.is_local_pack(&manifest, &cartridges, &filter) => self.pushed_cartridges(&cartridges, &filter),
+                _ => println!("Archive is not a pack of the local cartridges, the offline queue and the synced manifest are kept"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pushes the archive, optionally into a new code version that is created first
+    fn deploy(&self, archive: Vec<u8>, cartridges: &Vec<String>, fresh_code_version: Option<&str>) -> Result<(), String> {
+        let mut sandbox = self.arc_sandbox.lock().unwrap();
+
+        let code_version = match fresh_code_version {
+            Some(code_version) => code_version,
+            None => return self.push_archive(&sandbox, archive, Some(cartridges)),
+        };
+
+        println!("[{}] Creating code version", code_version);
+        sandbox.create_code_version(code_version).map_err(|message| format!("[{}] {}", code_version, message))?;
+
+        let active_code_version = sandbox.get_code_version();
+        sandbox.set_code_version(code_version);
+        let result = self.push_archive(&sandbox, archive, None);
+        sandbox.set_code_version(active_code_version.as_str());
+
+        if result.is_ok() {
+            println!("[{}] Pushed, activate the code version to use it", code_version);
+        }
+
+        result
+    }
+
+    fn push_archive(&self, sandbox: &sandbox::Sandbox, archive: Vec<u8>, replaced: Option<&Vec<String>>) -> Result<(), String> {
+        let remote_zip_path = format!("/cartridges-{}.zip", time::Time::new().timestamp());
+
+        println!("[{}] Sending zip to remote", remote_zip_path);
+        sandbox.send_collection(archive, remote_zip_path.as_str())?;
+
+        // the remote folders are left untouched until the zip is on the Sandbox
+        if let Some(cartridges) = replaced {
+            for collection_name in cartridges.iter() {
+                println!("[{}] Deleting remote folder", collection_name);
+                if let Err(message) = sandbox.delete_remote_collection(format!("/{}", collection_name).as_str()) {
+                    println!("[{}] {}", collection_name, message);
+                }
+            }
+        }
+
+        println!("[{}] Unzipping remote zip", remote_zip_path);
+        if let Err(e) = sandbox.unzip_remote_zip(remote_zip_path.as_str()) {
+            // keep the zip so the cartridges can be restored by unzipping it again
+            return Err(format!("Unable to unzip {}: {}", remote_zip_path, e));
+        }
+
+        println!("[{}] Deleting remote zip", remote_zip_path);
+        if let Err(message) = sandbox.delete_remote_collection(remote_zip_path.as_str()) {
+            println!("[{}] {}", remote_zip_path, message);
+        }
+
+        Ok(())
+    }
+
+    /// Handles SIGINT and SIGTERM, the running push or watch stops safely instead of being killed
+    /// A second signal exits immediately
+    pub fn handle_signals(&mut self) -> Result<(), String> {
+        self.shutdown = Some(Shutdown::install()?);
+        Ok(())
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.as_ref().map(|shutdown| shutdown.is_requested()).unwrap_or(false)
+    }
+
+    /// Watches until SIGINT or SIGTERM is received
+    /// The pending batch is pushed unless discard_pending is set
+    /// Returns the exit code of the session
+    pub fn watch_until_signal(&mut self, discard_pending: bool) -> i32 {
+        if self.shutdown.is_none() {
+            if let Err(message) = self.handle_signals() {
+                println!("{}", message);
+                return EXIT_FAILED;
+            }
+        }
+
+        let mut summary = Summary::new();
+        let handle = self.watch();
+        let control = Arc::clone(&handle.control);
+        let shutdown = self.shutdown.clone().unwrap();
+
+        // the watch can also end on its own, e.g. the watcher fails
+        while !shutdown.is_requested() && handle.is_running() {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        let pending = handle.status().pending;
+        let interrupted = shutdown.is_requested();
+        if !interrupted {
+            println!("Watch stopped unexpectedly");
+            handle.join();
+        } else if discard_pending {
+            println!("Discarding {} pending changes", pending);
+            handle.discard();
+        } else {
+            handle.stop();
+        }
+
+        let status = control.status.lock().unwrap().clone();
+        summary.pushed = status.pushed;
+        summary.failed = status.failed;
+        summary.skipped = status.skipped + if interrupted && discard_pending { pending } else { 0 };
+        summary.interrupted = interrupted;
+        summary.print();
+
+        if interrupted {
+            summary.exit_code()
+        } else {
+            EXIT_FAILED
+        }
+    }
+}
+
+/// Pushes the change set, queued operations are pushed first so the order of the changes is kept
+/// Returns the pushed operations with the uploaded content and the ones that failed while the Sandbox was reachable
+/// While the Sandbox is unreachable the change set is queued until the next probe is due
+fn deliver(sandbox: &sandbox::Sandbox, queue: &mut Queue, backoff: &mut Backoff, change_set: &lot::ChangeSet, fallback: &dyn Fn(&lot::Rename) -> lot::ChangeSet) -> (lot::ChangeSet, Uploaded, Vec<(lot::Operation, String)>) {
+    if !queue.is_empty() {
+        queue.push_change_set(change_set);
+        return replay(sandbox, queue, backoff, fallback);
+    }
+
+    let (pushed, uploaded, failed) = push_with_fallback(sandbox, change_set, fallback);
+    let failed = keep_failed(sandbox, queue, failed);
+    if !queue.is_empty() {
+        backoff.probed(false);
+    }
+
+    (pushed, uploaded, failed)
+}
+
+/// Pushes the queued operations if the next probe is due and the Sandbox is reachable
+fn replay(sandbox: &sandbox::Sandbox, queue: &mut Queue, backoff: &mut Backoff, fallback: &dyn Fn(&lot::Rename) -> lot::ChangeSet) -> (lot::ChangeSet, Uploaded, Vec<(lot::Operation, String)>) {
+    if queue.is_empty() || !backoff.is_due() {
+        return (lot::ChangeSet::empty(queue.base_path()), Uploaded::new(), vec![]);
+    }
+
+    if !sandbox.is_reachable() {
+        backoff.probed(false);
+        return (lot::ChangeSet::empty(queue.base_path()), Uploaded::new(), vec![]);
+    }
+
+    let change_set = queue.take();
+    println!("[Q {}] Replaying {} queued operations", time::Time::new().current().get_time(), change_set.len());
+
+    let (pushed, uploaded, failed) = push_with_fallback(sandbox, &change_set, fallback);
+    let failed = keep_failed(sandbox, queue, failed);
+    backoff.probed(queue.is_empty());
+
+    (pushed, uploaded, failed)
+}
+
+/// Pushes the change set, the target of a rename the reachable Sandbox could not move is uploaded instead
+/// The rename still fails if the target has no files or one of them could not be uploaded
+fn push_with_fallback(sandbox: &sandbox::Sandbox, change_set: &lot::ChangeSet, fallback: &dyn Fn(&lot::Rename) -> lot::ChangeSet) -> (lot::ChangeSet, Uploaded, Vec<(lot::Operation, String)>) {
+    let (failed, mut uploaded) = sandbox.push_change_set(change_set);
+    let mut pushed = change_set.without(&failed);
+
+    let has_renames = failed.iter().any(|(operation, _)| matches!(operation, lot::Operation::Rename(_)));
+    if !has_renames || !sandbox.is_reachable() {
+        return (pushed, uploaded, failed);
+    }
+
+    let mut still_failed = vec![];
+    for (operation, message) in failed.into_iter() {
+        let targets = match &operation {
+            lot::Operation::Rename(rename) => fallback(rename),
+            _ => {
+                still_failed.push((operation, message));
+                continue;
+            },
+        };
+        if targets.is_empty() {
+            still_failed.push((operation, message));
+            continue;
+        }
+
+        println!("[R {}] Uploading the renamed files instead", time::Time::new().current().get_time());
+        let (failed_uploads, target_uploaded) = sandbox.push_change_set(&targets);
+        pushed.stages.extend(targets.without(&failed_uploads).stages);
+        uploaded.extend(target_uploaded);
+
+        if !failed_uploads.is_empty() {
+            still_failed.push((operation, message));
+        }
+    }
+
+    (pushed, uploaded, still_failed)
+}
+
+/// Queues the failed operations when the Sandbox is unreachable
+/// Otherwise they failed for another reason (e.g. permissions) and are returned
+fn keep_failed(sandbox: &sandbox::Sandbox, queue: &mut Queue, failed: Vec<(lot::Operation, String)>) -> Vec<(lot::Operation, String)> {
+    if failed.is_empty() || sandbox.is_reachable() {
+        return failed;
+    }
+
+    println!("[Q {}] Sandbox unreachable, queued {} operations", time::Time::new().current().get_time(), failed.len());
+    queue.push(failed.into_iter().map(|(operation, _)| operation).collect());
+
+    vec![]
+}
+
+fn save_manifest_at(manifest: &Manifest, path: &str) -> () {
+    if let Err(message) = manifest.save(path) {
+        println!("{}", message);
+    }
+}
+
+fn update_status(control: &Control, pushed: usize, failed: &Vec<(lot::Operation, String)>, queued: usize) -> () {
+    let mut status = control.status.lock().unwrap();
+    status.pushed += pushed;
+    status.failed += failed.len();
+    status.queued = queued;
+    if let Some((_, message)) = failed.last() {
+        status.last_error = Some(message.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests;
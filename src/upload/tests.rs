@@ -0,0 +1,334 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::thread;
+
+use super::Uploader;
+use super::super::filter::Filter;
+use super::super::lot::{self, Rename};
+use super::super::watch;
+use super::super::event::FileEvent;
+use super::super::symlinks::Symlinks;
+use super::super::archive::{top_level_folders, zip_files, ArchiveManifest, sha256};
+
+/// Cartridges app_core and app_storefront with a config listing them, nothing is sent to the Sandbox
+fn uploader(name: &str) -> (PathBuf, Uploader) {
+    uploader_with(name, serde_json::json!({}))
+}
+
+/// Same uploader with more settings in its config
+fn uploader_with(name: &str, settings: serde_json::Value) -> (PathBuf, Uploader) {
+    let base = base_path(name);
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(base.join("cartridges/app_core/cartridge/scripts")).unwrap();
+    fs::create_dir_all(base.join("cartridges/app_storefront/cartridge/templates")).unwrap();
+    fs::write(base.join("cartridges/app_core/cartridge/scripts/home.js"), "home").unwrap();
+    fs::write(base.join("cartridges/app_storefront/cartridge/templates/home.isml"), "<isif>").unwrap();
+
+    let mut config = serde_json::json!({
+        "hostname": "sandbox.invalid",
+        "username": "user",
+        "password": "password",
+        "cartridges_path": base.join("cartridges"),
+        "cartridges": ["app_core", "app_storefront"],
+        "code_version": "version1",
+    });
+    for (key, value) in settings.as_object().unwrap().iter() {
+        config[key] = value.clone();
+    }
+    fs::write(base.join("config.json"), config.to_string()).unwrap();
+
+    let uploader = Uploader::new(base.join("config.json").to_str().unwrap());
+    (base, uploader)
+}
+
+fn base_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rustyuploader-upload-{}-{}", name, std::process::id()))
+}
+
+/// Zip with a file at each entry name
+fn zip_with(base: &PathBuf, names: &[&str]) -> String {
+    let files: Vec<(String, Vec<u8>)> = names.iter()
+        .map(|name| (name.to_string(), b"file".to_vec()))
+        .collect();
+
+    let path = base.join("custom.zip");
+    fs::write(&path, zip_files(&files).unwrap()).unwrap();
+
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn pack_writes_a_zip_per_cartridge_or_a_combined_one() {
+    let (base, uploader) = uploader("pack");
+    let output = base.join("output");
+
+    let written = uploader.pack(output.to_str().unwrap(), false).unwrap();
+    assert_eq!(written, vec![
+        format!("{}/app_core.zip", output.to_str().unwrap()),
+        format!("{}/app_storefront.zip", output.to_str().unwrap()),
+    ]);
+    for path in written.iter() {
+        let manifest = ArchiveManifest::load(format!("{}.manifest.json", path).as_str()).unwrap();
+        assert_eq!(manifest.sha256, sha256(&fs::read(path).unwrap()));
+    }
+
+    let combined = uploader.pack(output.to_str().unwrap(), true).unwrap();
+    assert_eq!(combined, vec![format!("{}/version1.zip", output.to_str().unwrap())]);
+    assert_eq!(top_level_folders(&fs::read(&combined[0]).unwrap()).unwrap(), vec!["app_core", "app_storefront"]);
+
+    // packing the same files again gives the same archive
+    let bytes = fs::read(&combined[0]).unwrap();
+    uploader.pack(output.to_str().unwrap(), true).unwrap();
+    assert!(fs::read(&combined[0]).unwrap() == bytes);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn deploy_archive_dry_run() {
+    let (base, mut uploader) = uploader("deploy");
+    let combined = uploader.pack(base.join("output").to_str().unwrap(), true).unwrap();
+
+    uploader.set_dry_run(true);
+    assert_eq!(uploader.deploy_archive(combined[0].as_str(), None), Ok(()));
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn deploy_archive_must_match_its_manifest() {
+    let (base, uploader) = uploader("mismatch");
+    let combined = uploader.pack(base.join("output").to_str().unwrap(), true).unwrap();
+    fs::write(&combined[0], zip_files(&vec![
+        ("app_core/cartridge/scripts/home.js".to_owned(), b"changed".to_vec()),
+    ]).unwrap()).unwrap();
+
+    assert!(uploader.deploy_archive(combined[0].as_str(), None).is_err());
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn deploy_archive_replaces_only_configured_cartridges() {
+    let (base, uploader) = uploader("folders");
+
+    for names in [
+        vec!["../app_core/file.js"],
+        vec!["./file.js"],
+        vec!["app_core/file.js", "app_other/file.js"],
+        vec!["app_core\\file.js"],
+        vec!["file.js"],
+    ].iter() {
+        let path = zip_with(&base, names);
+        assert!(uploader.deploy_archive(path.as_str(), None).is_err(), "{:?} was deployed", names);
+    }
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn only_a_pack_of_the_local_cartridges_counts_as_synced() {
+    let (base, uploader) = uploader("local-pack");
+    let combined = uploader.pack(base.join("output").to_str().unwrap(), true).unwrap();
+    let manifest = ArchiveManifest::load(format!("{}.manifest.json", combined[0]).as_str()).unwrap();
+    let cartridges = vec!["app_core".to_owned(), "app_storefront".to_owned()];
+    let filter = Filter::everything();
+
+    assert!(uploader.is_local_pack(&manifest, &cartridges, &filter));
+    assert!(!uploader.is_local_pack(&manifest, &vec!["app_core".to_owned()], &filter));
+
+    fs::write(base.join("cartridges/app_core/cartridge/scripts/home.js"), "changed").unwrap();
+    assert!(!uploader.is_local_pack(&manifest, &cartridges, &filter));
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn a_rename_falls_back_to_the_files_at_its_target() {
+    let (base, uploader) = uploader("rename-fallback");
+    fs::rename(base.join("cartridges/app_core/cartridge/scripts"), base.join("cartridges/app_core/cartridge/js")).unwrap();
+
+    let fallback = uploader.rename_fallback();
+    let targets = fallback(&Rename {
+        current: "/app_core/cartridge/scripts".to_owned(),
+        new: "/app_core/cartridge/js".to_owned(),
+    });
+    assert_eq!(targets.describe(), vec!["[1] [U] /app_core/cartridge/js/home.js"]);
+
+    assert!(fallback(&Rename { current: "/app_core/a.js".to_owned(), new: "/app_core/gone.js".to_owned() }).is_empty());
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn discovered_cartridges_push_their_files() {
+    let (base, uploader) = uploader("discover");
+    let cartridges_path = base.join("cartridges");
+    let mut watcher = watch::Sentry::spy_all(vec![watch::Target::flat(cartridges_path.to_str().unwrap())], Duration::from_millis(100), &watch::Backend::Notify, Symlinks::Content);
+    let mut collection = lot::Collection::init(cartridges_path.to_str().unwrap(), vec!["app_core".to_owned()], vec![]);
+
+    // copied in as a whole, the watcher reports the folder only
+    fs::create_dir_all(cartridges_path.join("app_new/cartridge")).unwrap();
+    fs::write(cartridges_path.join("app_new/cartridge/home.js"), "home").unwrap();
+    let created = watch::Event { root: Some(cartridges_path.clone()), event: FileEvent::Create(cartridges_path.join("app_new")) };
+    uploader.discover_cartridge(&mut watcher, &mut collection, &created);
+
+    assert!(watcher.is_watched(&cartridges_path.join("app_new")));
+    let uploads: Vec<String> = collection.get_data(&Filter::everything()).uploads().iter().map(|file| file.rel_path.clone()).collect();
+    assert_eq!(uploads, vec!["/app_new/cartridge/home.js"]);
+
+    fs::remove_dir_all(cartridges_path.join("app_new")).unwrap();
+    let removed = watch::Event { root: Some(cartridges_path.clone()), event: FileEvent::Remove(cartridges_path.join("app_new")) };
+    uploader.discover_cartridge(&mut watcher, &mut collection, &removed);
+
+    assert!(!watcher.is_watched(&cartridges_path.join("app_new")));
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+/// Polls the condition for a few seconds, the watcher reports events after its debounce delay
+fn eventually<F: Fn() -> bool>(condition: F) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    false
+}
+
+/// Writes the file until the condition holds, the watch starts watching in the background
+fn write_and_check<F: Fn() -> bool>(path: &PathBuf, condition: F) -> bool {
+    fs::write(path, "file").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    condition()
+}
+
+/// Batches handed to the upload thread, each one is saved as a change set
+fn batches(base: &PathBuf) -> usize {
+    fs::read_dir(base.join("batches")).unwrap().count()
+}
+
+#[test]
+fn watch_handle_pauses_flushes_and_stops() {
+    let (base, mut uploader) = uploader_with("handle", serde_json::json!({
+        "debounce_ms": 50,
+        "quiet_period_ms": 100,
+        "change_sets_path": base_path("handle").join("batches"),
+    }));
+    fs::create_dir_all(base.join("batches")).unwrap();
+    let scripts = base.join("cartridges/app_core/cartridge/scripts");
+    uploader.set_dry_run(true);
+
+    let handle = uploader.watch();
+    assert!(handle.is_running());
+
+    // collected but not pushed while paused, even after several quiet periods
+    handle.pause();
+    assert!(handle.status().paused);
+    assert!(eventually(|| write_and_check(&scripts.join("paused.js"), || handle.status().pending > 0)));
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(batches(&base), 0);
+
+    handle.flush_now();
+    assert!(eventually(|| batches(&base) == 1));
+    assert_eq!(handle.status().pending, 0);
+    assert!(handle.status().paused);
+
+    handle.resume();
+    assert!(!handle.status().paused);
+    fs::write(scripts.join("resumed.js"), "resumed").unwrap();
+    assert!(eventually(|| batches(&base) == 2));
+
+    // the pending changes are pushed before stopping
+    handle.pause();
+    assert!(eventually(|| write_and_check(&scripts.join("stopped.js"), || handle.status().pending > 0)));
+    handle.stop();
+    assert_eq!(batches(&base), 3);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn watch_handle_discards_the_pending_changes() {
+    let (base, mut uploader) = uploader_with("discard", serde_json::json!({
+        "debounce_ms": 50,
+        "quiet_period_ms": 100,
+        "change_sets_path": base_path("discard").join("batches"),
+    }));
+    fs::create_dir_all(base.join("batches")).unwrap();
+    uploader.set_dry_run(true);
+
+    let handle = uploader.watch();
+    handle.pause();
+    let discarded = base.join("cartridges/app_core/cartridge/scripts/discarded.js");
+    assert!(eventually(|| write_and_check(&discarded, || handle.status().pending > 0)));
+    handle.discard();
+    assert_eq!(batches(&base), 0);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn synced_manifest_is_kept_next_to_the_config() {
+    let (base, mut uploader) = uploader_with("manifest-path", serde_json::json!({ "startup_sync": "manifest" }));
+    assert_eq!(uploader.manifest_path(), Some(base.join("synced_manifest.json").to_str().unwrap().to_owned()));
+
+    uploader.set_dry_run(true);
+    assert_eq!(uploader.manifest_path(), None);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn batches_wait_for_a_quiet_period() {
+    let (base, mut uploader) = uploader_with("quiet", serde_json::json!({
+        "debounce_ms": 20,
+        "quiet_period_ms": 600,
+        "change_sets_path": base_path("quiet").join("batches"),
+    }));
+    fs::create_dir_all(base.join("batches")).unwrap();
+    uploader.set_dry_run(true);
+
+    let handle = uploader.watch();
+    let home = base.join("cartridges/app_core/cartridge/scripts/home.js");
+    assert!(eventually(|| write_and_check(&home, || handle.status().pending > 0)));
+    assert_eq!(batches(&base), 0);
+
+    assert!(eventually(|| batches(&base) == 1));
+    assert_eq!(handle.status().pending, 0);
+
+    handle.stop();
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn old_batches_are_pushed_while_events_keep_coming() {
+    let (base, mut uploader) = uploader_with("batch-age", serde_json::json!({
+        "debounce_ms": 20,
+        "quiet_period_ms": 2000,
+        "max_batch_age_ms": 300,
+        "change_sets_path": base_path("batch-age").join("batches"),
+    }));
+    fs::create_dir_all(base.join("batches")).unwrap();
+    uploader.set_dry_run(true);
+    let scripts = base.join("cartridges/app_core/cartridge/scripts");
+
+    let handle = uploader.watch();
+    assert!(eventually(|| write_and_check(&scripts.join("home.js"), || handle.status().pending > 0 || batches(&base) > 0)));
+    let before = batches(&base);
+
+    // never quiet for a whole quiet period
+    for index in 0..20 {
+        fs::write(scripts.join(format!("file{}.js", index)), "file").unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(batches(&base) > before);
+
+    handle.stop();
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,343 @@
+extern crate notify;
+
+use notify::{Watcher, RecursiveMode, watcher, RecommendedWatcher};
+use std::sync::mpsc::{channel, Receiver, Sender, RecvTimeoutError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::collections::VecDeque;
+use std::thread;
+use std::fs;
+
+use super::json::Config;
+use super::event::FileEvent;
+use super::poll::{Poller, Identity};
+use super::symlinks::Symlinks;
+
+/// Timings used to debounce events and group them into batches
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub debounce: Duration,
+    pub quiet_period: Duration,
+    pub max_batch_age: Duration,
+    pub settle: Option<Duration>,
+}
+
+impl Timing {
+    pub fn from_config(config: &Config) -> Self {
+        Timing {
+            debounce: Duration::from_millis(config.get_debounce_ms()),
+            quiet_period: Duration::from_millis(config.get_quiet_period_ms()),
+            max_batch_age: Duration::from_millis(config.get_max_batch_age_ms()),
+            settle: config.get_settle_ms().map(Duration::from_millis),
+        }
+    }
+}
+
+/// Source of the file system events
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// Native notifications (inotify, FSEvents, ReadDirectoryChangesW)
+    Notify,
+    /// Scans the targets at the interval and compares modification time and size
+    /// Works on network file systems and container volumes
+    Polling { interval: Duration, identity: Identity },
+}
+
+impl Backend {
+    pub fn from_config(config: &Config) -> Self {
+        match config.get_watch_backend().as_str() {
+            "polling" => Backend::Polling {
+                interval: Duration::from_millis(config.get_poll_interval_ms()),
+                identity: Identity::from_name(config.get_rename_detection().as_str()),
+            },
+            _ => Backend::Notify,
+        }
+    }
+}
+
+enum Watch {
+    Notify(RecommendedWatcher),
+    Polling(Poller),
+}
+
+/// A path watched by the Sentry together with its recursive mode
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub path: PathBuf,
+    pub recursive: bool,
+}
+
+impl Target {
+    pub fn recursive(path: &str) -> Self {
+        Target { path: PathBuf::from(path), recursive: true }
+    }
+
+    pub fn flat(path: &str) -> Self {
+        Target { path: PathBuf::from(path), recursive: false }
+    }
+
+    fn mode(&self) -> RecursiveMode {
+        if self.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        }
+    }
+}
+
+/// Event received from the watcher tagged with the root it came from
+/// root is None for events without a path (e.g. Rescan)
+#[derive(Debug)]
+pub struct Event {
+    pub root: Option<PathBuf>,
+    pub event: FileEvent,
+}
+
+pub struct Sentry {
+    watcher: Watch,
+    channel_rx: Receiver<FileEvent>,
+    targets: Vec<Target>,
+    symlinks: Symlinks,
+    /// Link targets watched on their own with the link they are reached through
+    links: Vec<(PathBuf, PathBuf)>,
+    /// Events seen through several links, returned by the next calls to recv
+    pending: VecDeque<Event>,
+}
+
+impl Sentry {
+    /// Watch every target, each one with its own recursive mode
+    /// Links accepted by the symlink policy are watched as well
+    pub fn spy_all(targets: Vec<Target>, debounce: Duration, backend: &Backend, symlinks: Symlinks) -> Self {
+        // create a channel to receive the events
+        let (tx, rx) = channel();
+
+        let watcher = match backend {
+            Backend::Notify => Watch::Notify(notify_watcher(tx, debounce)),
+            Backend::Polling { interval, identity } => Watch::Polling(Poller::start(tx, *interval, *identity, symlinks)),
+        };
+
+        let mut sentry = Sentry { watcher: watcher, channel_rx: rx, targets: vec![], symlinks: symlinks, links: vec![], pending: VecDeque::new() };
+
+        for target in targets.into_iter() {
+            if let Err(message) = sentry.add(target) {
+                println!("{}", message);
+            }
+        }
+
+        sentry
+    }
+
+    /// Start watching a new target at runtime
+    /// Adding an already watched path replaces its recursive mode, the previous watch is kept if that fails
+    pub fn add(&mut self, target: Target) -> Result<(), String> {
+        let was_recursive = self.targets.iter().any(|watched| watched.path == target.path && watched.recursive);
+
+        match &mut self.watcher {
+            Watch::Notify(watcher) => watcher.watch(&target.path, target.mode())
+                .map_err(|e| format!("Unable to watch path: {:?}\nError: {}", target.path, e))?,
+            Watch::Polling(poller) => poller.watch(target.clone())?,
+        }
+
+        if was_recursive && !target.recursive {
+            self.unwatch_inside(&target.path);
+        }
+
+        if target.recursive {
+            self.watch_links(&target.path);
+        }
+        self.targets.retain(|watched| watched.path != target.path);
+        self.targets.push(target);
+
+        Ok(())
+    }
+
+    /// Stops watching the folders and link targets below the path, the path itself stays watched
+    /// Folders watched as targets on their own are kept
+    fn unwatch_inside(&mut self, path: &Path) -> () {
+        let removed = self.drop_links(path);
+
+        // the folders of a recursive watch are removed together with their own subfolders
+        let folders: Vec<PathBuf> = fs::read_dir(path).into_iter()
+            .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+            .map(|entry| entry.path())
+            .filter(|entry| entry.is_dir() && !self.is_watched(entry))
+            .chain(removed.into_iter())
+            .collect();
+
+        if let Watch::Notify(watcher) = &mut self.watcher {
+            for folder in folders.iter() {
+                let _ = watcher.unwatch(folder);
+            }
+        }
+    }
+
+    /// Watches the targets of the links found under the path, e.g. a link created while watching
+    /// Native watchers do not follow links, the polling backend scans through them already
+    pub fn watch_links(&mut self, path: &Path) -> () {
+        let watcher = match &mut self.watcher {
+            Watch::Notify(watcher) => watcher,
+            Watch::Polling(_) => return,
+        };
+
+        for (target, link) in self.symlinks.links(path).into_iter() {
+            if self.links.iter().any(|(_, watched)| watched == &link) {
+                continue;
+            }
+
+            match watcher.watch(&target, RecursiveMode::Recursive) {
+                Ok(()) => self.links.push((target, link)),
+                Err(e) => println!("Unable to watch link target: {:?}\nError: {}", target, e),
+            }
+        }
+    }
+
+    /// Stops watching the targets of the links at or below the path, e.g. a link that was removed
+    pub fn unwatch_links(&mut self, path: &Path) -> () {
+        let removed = self.drop_links(path);
+
+        if let Watch::Notify(watcher) = &mut self.watcher {
+            for target in removed.into_iter() {
+                let _ = watcher.unwatch(&target);
+            }
+        }
+    }
+
+    /// Forgets the links at or below the path
+    /// Returns their targets that no other link is reached through anymore
+    fn drop_links(&mut self, path: &Path) -> Vec<PathBuf> {
+        let (removed, links): (Vec<(PathBuf, PathBuf)>, Vec<(PathBuf, PathBuf)>) = self.links.drain(..)
+            .partition(|(_, link)| link.starts_with(path));
+        self.links = links;
+
+        let mut targets: Vec<PathBuf> = removed.into_iter()
+            .map(|(target, _)| target)
+            .filter(|target| !self.links.iter().any(|(watched, _)| watched == target))
+            .collect();
+        targets.sort();
+        targets.dedup();
+
+        targets
+    }
+
+    /// Stop watching a target at runtime
+    pub fn remove(&mut self, path: &Path) -> Result<(), String> {
+        self.targets.retain(|target| target.path != path);
+        self.unwatch_links(path);
+
+        match &mut self.watcher {
+            Watch::Notify(watcher) => watcher.unwatch(path)
+                .map_err(|e| format!("Unable to unwatch path: {:?}\nError: {}", path, e)),
+            Watch::Polling(poller) => poller.unwatch(path),
+        }
+    }
+
+    pub fn is_watched(&self, path: &Path) -> bool {
+        self.targets.iter().any(|target| target.path == path)
+    }
+
+    /// Waits for the next event at most the given amount of time
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Event, RecvTimeoutError> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        let event = self.channel_rx.recv_timeout(timeout)?;
+        Ok(self.tag(event))
+    }
+
+    /// Returns the first event, the ones seen through the other links are kept for the next calls
+    fn tag(&mut self, event: FileEvent) -> Event {
+        // a removed or moved link does not lead to its target anymore
+        match &event {
+            FileEvent::Remove(path) | FileEvent::Rename(path, _) => self.unwatch_links(path),
+            _ => (),
+        }
+
+        let mut events: VecDeque<Event> = self.through_links(event).into_iter()
+            .map(|event| Event { root: event.path().and_then(|path| self.root_of(path)), event: event })
+            .collect();
+        let first = events.pop_front().unwrap();
+        self.pending.extend(events);
+
+        first
+    }
+
+    /// The event as seen through every link whose target contains its path, one event per link
+    /// Paths under a watched target are real paths and are kept as they are
+    fn through_links(&self, event: FileEvent) -> Vec<FileEvent> {
+        let links: Vec<&(PathBuf, PathBuf)> = match event.path() {
+            Some(path) if self.root_of(path).is_none() => self.links.iter().filter(|(target, _)| path.starts_with(target)).collect(),
+            _ => vec![],
+        };
+
+        if links.is_empty() {
+            return vec![event];
+        }
+
+        links.into_iter()
+            .map(|(target, link)| event.clone().map_paths(|path| match path.strip_prefix(target) {
+                Ok(inside) => link.join(inside),
+                Err(_) => path,
+            }))
+            .collect()
+    }
+
+    /// Returns the deepest watched target that contains the path
+    pub fn root_of(&self, path: &Path) -> Option<PathBuf> {
+        self.targets.iter()
+            .filter(|target| path.starts_with(&target.path))
+            .filter(|target| target.recursive || path.parent() == Some(target.path.as_path()) || path == target.path)
+            .max_by_key(|target| target.path.components().count())
+            .map(|target| target.path.clone())
+    }
+}
+
+/// Creates a notify watcher forwarding its events as FileEvents
+/// The forwarding thread ends when the watcher is dropped
+fn notify_watcher(tx: Sender<FileEvent>, debounce: Duration) -> RecommendedWatcher {
+    let (notify_tx, notify_rx) = channel();
+
+    // Create a watcher object, delivering debounced events.
+    let watcher = watcher(notify_tx, debounce).unwrap();
+
+    thread::spawn(move || {
+        for event in notify_rx.iter() {
+            if let Some(event) = FileEvent::from_notify(event) {
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    watcher
+}
+
+/// Waits until the files stop changing size
+/// Returns false if the files are still changing after max_wait
+pub fn settle(paths: &Vec<String>, interval: Duration, max_wait: Duration) -> bool {
+    let start = Instant::now();
+    let mut sizes = file_sizes(paths);
+
+    while start.elapsed() < max_wait {
+        thread::sleep(interval);
+        let current = file_sizes(paths);
+
+        if current == sizes {
+            return true;
+        }
+
+        sizes = current;
+    }
+
+    false
+}
+
+fn file_sizes(paths: &Vec<String>) -> Vec<Option<u64>> {
+    paths.iter()
+        .map(|path| fs::metadata(path).ok().map(|metadata| metadata.len()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
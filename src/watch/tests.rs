@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{Sentry, Target, Backend};
+use super::super::event::FileEvent;
+use super::super::symlinks::Symlinks;
+
+/// Cartridge app with the links assets and images to the same shared folder
+#[cfg(unix)]
+fn linked_cartridge(name: &str) -> (PathBuf, Sentry) {
+    use std::os::unix::fs::symlink;
+
+    let base = std::env::temp_dir().join(format!("rustyuploader-watch-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(base.join("app")).unwrap();
+    fs::create_dir_all(base.join("shared/assets")).unwrap();
+    symlink(base.join("shared/assets"), base.join("app/assets")).unwrap();
+    symlink(base.join("shared/assets"), base.join("app/images")).unwrap();
+    let base = fs::canonicalize(&base).unwrap();
+
+    let target = Target::recursive(base.join("app").to_str().unwrap());
+    let sentry = Sentry::spy_all(vec![target], Duration::from_millis(100), &Backend::Notify, Symlinks::Follow);
+
+    (base, sentry)
+}
+
+#[cfg(unix)]
+#[test]
+fn events_are_seen_through_every_link() {
+    let (base, sentry) = linked_cartridge("through");
+
+    let mut events = sentry.through_links(FileEvent::Write(base.join("shared/assets/logo.svg")));
+    events.sort_by_key(|event| format!("{:?}", event));
+    assert_eq!(events, vec![
+        FileEvent::Write(base.join("app/assets/logo.svg")),
+        FileEvent::Write(base.join("app/images/logo.svg")),
+    ]);
+
+    // a real path is never rewritten
+    assert_eq!(sentry.through_links(FileEvent::Write(base.join("app/home.js"))), vec![FileEvent::Write(base.join("app/home.js"))]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn watched_targets_are_not_rewritten() {
+    let (base, mut sentry) = linked_cartridge("watched");
+    sentry.add(Target::recursive(base.join("shared").to_str().unwrap())).unwrap();
+
+    let event = FileEvent::Write(base.join("shared/assets/logo.svg"));
+    assert_eq!(sentry.through_links(event.clone()), vec![event]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn removed_links_are_forgotten() {
+    let (base, mut sentry) = linked_cartridge("removed");
+    assert_eq!(sentry.links.len(), 2);
+
+    fs::remove_file(base.join("app/assets")).unwrap();
+    let event = sentry.tag(FileEvent::Remove(base.join("app/assets")));
+    assert_eq!(event.event, FileEvent::Remove(base.join("app/assets")));
+    assert_eq!(event.root, Some(base.join("app")));
+
+    let links: Vec<&PathBuf> = sentry.links.iter().map(|(_, link)| link).collect();
+    assert_eq!(links, vec![&base.join("app/images")]);
+    assert_eq!(sentry.through_links(FileEvent::Write(base.join("shared/assets/logo.svg"))), vec![
+        FileEvent::Write(base.join("app/images/logo.svg")),
+    ]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn targets_are_added_and_removed_at_runtime() {
+    let base = std::env::temp_dir().join(format!("rustyuploader-watch-targets-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(base.join("cartridges/app/cartridge")).unwrap();
+    let base = fs::canonicalize(&base).unwrap();
+    let cartridges = base.join("cartridges");
+    let app = cartridges.join("app");
+
+    let mut sentry = Sentry::spy_all(vec![Target::flat(cartridges.to_str().unwrap())], Duration::from_millis(100), &Backend::Notify, Symlinks::Content);
+    sentry.add(Target::recursive(app.to_str().unwrap())).unwrap();
+    assert!(sentry.is_watched(&app));
+
+    // the deepest target wins, a flat target only holds its direct children
+    assert_eq!(sentry.root_of(&app.join("cartridge/home.js")), Some(app.clone()));
+    assert_eq!(sentry.root_of(&app), Some(app.clone()));
+    assert_eq!(sentry.root_of(&cartridges.join("other")), Some(cartridges.clone()));
+    assert_eq!(sentry.root_of(&cartridges.join("other/home.js")), None);
+    assert_eq!(sentry.root_of(&base.join("outside.js")), None);
+
+    sentry.remove(&app).unwrap();
+    assert!(!sentry.is_watched(&app));
+    assert_eq!(sentry.root_of(&app.join("cartridge/home.js")), None);
+    assert_eq!(sentry.root_of(&app), Some(cartridges.clone()));
+
+    // adding a watched path again replaces its mode
+    sentry.add(Target::recursive(cartridges.to_str().unwrap())).unwrap();
+    assert_eq!(sentry.targets.len(), 1);
+    assert_eq!(sentry.root_of(&cartridges.join("other/home.js")), Some(cartridges.clone()));
+    sentry.add(Target::flat(cartridges.to_str().unwrap())).unwrap();
+    assert_eq!(sentry.root_of(&cartridges.join("other/home.js")), None);
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn settle_waits_for_files_to_stop_growing() {
+    use std::io::Write;
+    use std::thread;
+    use std::time::Instant;
+    use super::settle;
+
+    let base = std::env::temp_dir().join(format!("rustyuploader-settle-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(&base).unwrap();
+    fs::write(base.join("stable.js"), "stable").unwrap();
+    fs::write(base.join("bundle.js"), "").unwrap();
+    let stable = vec![base.join("stable.js").to_str().unwrap().to_owned()];
+    let growing = vec![base.join("bundle.js").to_str().unwrap().to_owned()];
+
+    let start = Instant::now();
+    assert!(settle(&stable, Duration::from_millis(50), Duration::from_secs(5)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // a bundle still being written for longer than the wait
+    let path = base.join("bundle.js");
+    let writer = thread::spawn(move || {
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        for _ in 0..40 {
+            file.write_all(b"chunk").unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+    assert!(!settle(&growing, Duration::from_millis(50), Duration::from_millis(300)));
+
+    // settled once the writer is done
+    writer.join().unwrap();
+    assert!(settle(&growing, Duration::from_millis(50), Duration::from_secs(5)));
+
+    fs::remove_dir_all(&base).unwrap();
+}